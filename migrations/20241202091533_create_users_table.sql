@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Create Users Table
+CREATE TABLE users(
+user_id uuid PRIMARY KEY,
+username TEXT NOT NULL UNIQUE,
+password_hash TEXT NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Tombstones for erased subscribers. Only a hash of the address is kept, so that
+-- erased addresses can be recognised without storing them.
+CREATE TABLE erased_subscribers(
+email_hash TEXT NOT NULL,
+PRIMARY KEY (email_hash),
+erased_at timestamptz NOT NULL,
+erased_by TEXT NOT NULL,
+erased_subscriptions INTEGER NOT NULL
+);
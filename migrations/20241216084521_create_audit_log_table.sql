@@ -0,0 +1,24 @@
+-- Add migration script here
+-- Create Audit Log Table
+CREATE TABLE audit_log(
+id BIGSERIAL PRIMARY KEY,
+occurred_at timestamptz NOT NULL,
+actor TEXT NOT NULL,
+action TEXT NOT NULL,
+target TEXT NULL,
+request_id TEXT NULL,
+before JSONB NULL,
+after JSONB NULL
+);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+-- The audit log is append-only: reject any attempt to rewrite history.
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_is_append_only
+BEFORE UPDATE OR DELETE ON audit_log
+FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
@@ -0,0 +1,9 @@
+-- Add migration script here
+BEGIN;
+  ALTER TABLE users ADD COLUMN role TEXT NULL;
+-- Users created before roles existed keep full access
+  UPDATE users SET role = 'admin' WHERE role IS NULL;
+  ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+  ALTER TABLE users ADD CONSTRAINT users_role_check
+    CHECK (role IN ('viewer', 'editor', 'publisher', 'admin'));
+COMMIT;
@@ -0,0 +1,23 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+
+-- Tokens are stored as SHA-256 hashes: the plain token only ever exists in the email.
+CREATE TABLE user_invitations(
+token_hash TEXT NOT NULL,
+PRIMARY KEY (token_hash),
+email TEXT NOT NULL,
+role TEXT NOT NULL CHECK (role IN ('viewer', 'editor', 'publisher', 'admin')),
+invited_by uuid NOT NULL REFERENCES users (user_id),
+created_at timestamptz NOT NULL,
+expires_at timestamptz NOT NULL,
+accepted_at timestamptz NULL
+);
+
+CREATE TABLE password_reset_tokens(
+token_hash TEXT NOT NULL,
+PRIMARY KEY (token_hash),
+user_id uuid NOT NULL REFERENCES users (user_id),
+created_at timestamptz NOT NULL,
+expires_at timestamptz NOT NULL,
+used_at timestamptz NULL
+);
@@ -0,0 +1,31 @@
+-- Add migration script here
+-- A session is pending until its second factor has been verified.
+CREATE TABLE admin_sessions(
+token_hash TEXT NOT NULL,
+PRIMARY KEY (token_hash),
+user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+created_at timestamptz NOT NULL,
+expires_at timestamptz NOT NULL,
+second_factor_pending BOOLEAN NOT NULL
+);
+CREATE INDEX admin_sessions_user_id_idx ON admin_sessions (user_id);
+
+-- The TOTP secret must be readable to verify codes, so it cannot be hashed.
+-- `enabled_at` stays NULL until enrolment has been confirmed with a first code.
+CREATE TABLE user_totp(
+user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+PRIMARY KEY (user_id),
+secret TEXT NOT NULL,
+created_at timestamptz NOT NULL,
+enabled_at timestamptz NULL,
+last_used_step BIGINT NULL,
+failed_attempts INTEGER NOT NULL DEFAULT 0,
+locked_until timestamptz NULL
+);
+
+CREATE TABLE user_recovery_codes(
+user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+code_hash TEXT NOT NULL,
+PRIMARY KEY (user_id, code_hash),
+used_at timestamptz NULL
+);
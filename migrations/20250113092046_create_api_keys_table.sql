@@ -0,0 +1,19 @@
+-- Add migration script here
+-- Keys are stored hashed: the plain key is only shown once, when it is created.
+-- `prefix` is kept in clear so that admins can tell keys apart.
+CREATE TABLE api_keys(
+id uuid NOT NULL,
+PRIMARY KEY (id),
+name TEXT NOT NULL,
+prefix TEXT NOT NULL,
+key_hash TEXT NOT NULL UNIQUE,
+scopes TEXT[] NOT NULL CHECK (
+    cardinality(scopes) > 0
+    AND scopes <@ ARRAY['subscribers:read', 'subscribers:write', 'newsletters:publish', 'audit:read']
+),
+created_by uuid NOT NULL REFERENCES users (user_id),
+created_at timestamptz NOT NULL,
+expires_at timestamptz NULL,
+last_used_at timestamptz NULL,
+revoked_at timestamptz NULL
+);
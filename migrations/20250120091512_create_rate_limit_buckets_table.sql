@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Token buckets of the Postgres-backed rate limiter, shared by all instances.
+-- `refilled_at` is when the bucket will be full again: rows past it carry no
+-- information and are deleted.
+CREATE TABLE rate_limit_buckets(
+key TEXT NOT NULL,
+PRIMARY KEY (key),
+tokens DOUBLE PRECISION NOT NULL,
+updated_at timestamptz NOT NULL,
+refilled_at timestamptz NOT NULL
+);
+CREATE INDEX rate_limit_buckets_refilled_at_idx ON rate_limit_buckets (refilled_at);
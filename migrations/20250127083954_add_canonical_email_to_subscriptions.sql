@@ -0,0 +1,50 @@
+-- Add migration script here
+-- `email` keeps the address as typed; `email_canonical` identifies the mailbox
+-- and is what uniqueness is enforced on. The backfill mirrors
+-- `SubscriberEmail::canonical`, except for the punycode encoding of
+-- internationalised domains, which Postgres cannot do.
+BEGIN;
+ALTER TABLE subscriptions ADD COLUMN email_canonical TEXT NULL;
+
+UPDATE subscriptions
+SET email_canonical = lower(trim(email));
+
+WITH parts AS (
+  SELECT id,
+    substring(email_canonical FROM '^(.*)@') AS local,
+    substring(email_canonical FROM '@([^@]*)$') AS domain
+  FROM subscriptions
+)
+UPDATE subscriptions s
+SET email_canonical = CASE
+    WHEN p.domain IN ('gmail.com', 'googlemail.com')
+      THEN replace(split_part(p.local, '+', 1), '.', '') || '@gmail.com'
+    ELSE split_part(p.local, '+', 1) || '@' || p.domain
+  END
+FROM parts p
+WHERE s.id = p.id
+  AND p.domain IN (
+    'gmail.com', 'googlemail.com', 'outlook.com', 'hotmail.com', 'live.com',
+    'icloud.com', 'me.com', 'fastmail.com', 'protonmail.com', 'proton.me'
+  );
+
+-- Keep one row per mailbox: confirmed subscriptions first, then the oldest.
+CREATE TEMPORARY TABLE duplicate_subscriptions ON COMMIT DROP AS
+SELECT id FROM (
+  SELECT id, row_number() OVER (
+    PARTITION BY email_canonical
+    ORDER BY status = 'confirmed' DESC, subscribed_at, id
+  ) AS position
+  FROM subscriptions
+) ranked
+WHERE position > 1;
+
+DELETE FROM subscription_tokens
+WHERE subscriber_id IN (SELECT id FROM duplicate_subscriptions);
+DELETE FROM subscriptions
+WHERE id IN (SELECT id FROM duplicate_subscriptions);
+
+ALTER TABLE subscriptions ALTER COLUMN email_canonical SET NOT NULL;
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_canonical_key ON subscriptions (email_canonical);
+COMMIT;
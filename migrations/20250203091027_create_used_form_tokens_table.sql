@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Nonces of the subscription form tokens already submitted: a token is good
+-- for a single submission. Rows past `expires_at` are for tokens refused as
+-- expired anyway, and are deleted.
+CREATE TABLE used_form_tokens(
+nonce TEXT NOT NULL,
+PRIMARY KEY (nonce),
+expires_at timestamptz NOT NULL
+);
+CREATE INDEX used_form_tokens_expires_at_idx ON used_form_tokens (expires_at);
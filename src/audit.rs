@@ -0,0 +1,71 @@
+use chrono::Utc;
+use sqlx::PgExecutor;
+
+/// An administrative action to append to the `audit_log` table.
+#[derive(Debug)]
+pub struct AuditEvent {
+    actor: String,
+    action: &'static str,
+    target: Option<String>,
+    request_id: Option<String>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+impl AuditEvent {
+    pub fn new(actor: impl Into<String>, action: &'static str) -> Self {
+        Self {
+            actor: actor.into(),
+            action,
+            target: None,
+            request_id: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn request_id(mut self, request_id: impl ToString) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    pub fn before(mut self, before: serde_json::Value) -> Self {
+        self.before = Some(before);
+        self
+    }
+
+    pub fn after(mut self, after: serde_json::Value) -> Self {
+        self.after = Some(after);
+        self
+    }
+}
+
+#[tracing::instrument(
+    name = "Record an audit log entry",
+    skip(executor, event),
+    fields(action = event.action, actor = %event.actor)
+)]
+pub async fn record_audit_event<'e>(
+    executor: impl PgExecutor<'e>,
+    event: AuditEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO audit_log (occurred_at, actor, action, target, request_id, before, after)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+        Utc::now(),
+        event.actor,
+        event.action,
+        event.target,
+        event.request_id,
+        event.before,
+        event.after,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use crate::authentication::{
+    generate_token, hash_token, AuthError, AuthenticatedUser, Permission, Role,
+};
+use actix_web::http::header;
+use actix_web::http::header::HeaderMap;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use uuid::Uuid;
+
+/// Makes keys easy to spot for secret scanners and in logs.
+const API_KEY_PREFIX: &str = "z2p_";
+/// Characters of a key kept in clear to tell keys apart.
+const VISIBLE_PREFIX_LENGTH: usize = API_KEY_PREFIX.len() + 8;
+
+/// What an API key may do, on top of what the role of its creator allows.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub enum Scope {
+    SubscribersRead,
+    SubscribersWrite,
+    NewslettersPublish,
+    AuditRead,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::SubscribersRead => "subscribers:read",
+            Scope::SubscribersWrite => "subscribers:write",
+            Scope::NewslettersPublish => "newsletters:publish",
+            Scope::AuditRead => "audit:read",
+        }
+    }
+
+    /// The scope granting `permission`, if API keys can be granted it at all.
+    pub fn for_permission(permission: Permission) -> Option<Self> {
+        match permission {
+            Permission::ReadSubscribers => Some(Scope::SubscribersRead),
+            Permission::EditSubscribers => Some(Scope::SubscribersWrite),
+            Permission::PublishNewsletters => Some(Scope::NewslettersPublish),
+            Permission::ReadAuditLog => Some(Scope::AuditRead),
+            Permission::ManageUsers | Permission::ManageApiKeys | Permission::ConfigureLogging => {
+                None
+            }
+        }
+    }
+}
+
+/// Scopes are documented as the strings they are (de)serialised as.
+impl utoipa::PartialSchema for Scope {
+    fn schema() -> RefOr<Schema> {
+        let scopes = [
+            Scope::SubscribersRead,
+            Scope::SubscribersWrite,
+            Scope::NewslettersPublish,
+            Scope::AuditRead,
+        ];
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .enum_values(Some(scopes.map(Scope::as_str)))
+            .into()
+    }
+}
+
+impl utoipa::ToSchema for Scope {}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.as_str().to_string()
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "subscribers:read" => Ok(Self::SubscribersRead),
+            "subscribers:write" => Ok(Self::SubscribersWrite),
+            "newsletters:publish" => Ok(Self::NewslettersPublish),
+            "audit:read" => Ok(Self::AuditRead),
+            other => Err(format!(
+                "{other} is not a supported scope. Use either `subscribers:read`, \
+                `subscribers:write`, `newsletters:publish` or `audit:read`."
+            )),
+        }
+    }
+}
+
+/// An API key as listed to admins. The key itself is never stored.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Stores a new API key and returns its id together with the plain key,
+/// which cannot be retrieved afterwards.
+#[tracing::instrument(name = "Create an API key", skip(executor))]
+pub async fn create_api_key<'e>(
+    executor: impl PgExecutor<'e>,
+    name: &str,
+    scopes: &[Scope],
+    created_by: Uuid,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(Uuid, String), anyhow::Error> {
+    let id = Uuid::new_v4();
+    let key = format!("{API_KEY_PREFIX}{}", generate_token());
+    let scopes: Vec<String> = scopes.iter().copied().map(String::from).collect();
+    sqlx::query!(
+        r#"INSERT INTO api_keys (id, name, prefix, key_hash, scopes, created_by, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        id,
+        name,
+        &key[..VISIBLE_PREFIX_LENGTH],
+        hash_token(&key),
+        &scopes,
+        created_by,
+        Utc::now(),
+        expires_at,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store a new API key.")?;
+    Ok((id, key))
+}
+
+#[tracing::instrument(name = "List API keys", skip(pool))]
+pub async fn list_api_keys(pool: &PgPool) -> Result<Vec<ApiKey>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT k.id, k.name, k.prefix, k.scopes, u.username AS created_by, k.created_at,
+            k.expires_at, k.last_used_at, k.revoked_at
+        FROM api_keys k JOIN users u ON u.user_id = k.created_by
+        ORDER BY k.created_at DESC"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch API keys.")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(ApiKey {
+                id: r.id,
+                name: r.name,
+                prefix: r.prefix,
+                scopes: parse_scopes(r.scopes)?,
+                created_by: r.created_by,
+                created_at: r.created_at,
+                expires_at: r.expires_at,
+                last_used_at: r.last_used_at,
+                revoked_at: r.revoked_at,
+            })
+        })
+        .collect()
+}
+
+/// Returns `false` if there is no such key, or if it was already revoked.
+#[tracing::instrument(name = "Revoke an API key", skip(executor))]
+pub async fn revoke_api_key<'e>(
+    executor: impl PgExecutor<'e>,
+    id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let revoked = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL",
+        Utc::now(),
+        id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke an API key.")?
+    .rows_affected();
+    Ok(revoked == 1)
+}
+
+/// Extracts the key from an `Authorization: Bearer` header, if there is one.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Authenticates an API key and records that it was used.
+/// Its permissions are bound by both its scopes and the current role of its creator.
+#[tracing::instrument(name = "Validate API key", skip(key, pool))]
+pub async fn validate_api_key(key: &str, pool: &PgPool) -> Result<AuthenticatedUser, AuthError> {
+    let row = sqlx::query!(
+        r#"UPDATE api_keys k SET last_used_at = now()
+        FROM users u
+        WHERE k.key_hash = $1
+            AND k.revoked_at IS NULL
+            AND (k.expires_at IS NULL OR k.expires_at > now())
+            AND u.user_id = k.created_by
+        RETURNING k.id, k.scopes, k.created_by, u.role"#,
+        hash_token(key)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to validate an API key.")?
+    .ok_or_else(|| {
+        AuthError::InvalidCredentials(anyhow::anyhow!("Unknown, expired or revoked API key."))
+    })?;
+    Ok(AuthenticatedUser {
+        user_id: row.created_by,
+        username: format!("api-key:{}", row.id),
+        role: Role::try_from(row.role).map_err(anyhow::Error::msg)?,
+        scopes: Some(parse_scopes(row.scopes)?),
+    })
+}
+
+fn parse_scopes(scopes: Vec<String>) -> Result<Vec<Scope>, anyhow::Error> {
+    scopes
+        .into_iter()
+        .map(|s| Scope::try_from(s).map_err(anyhow::Error::msg))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scope;
+    use crate::authentication::Permission;
+    use claims::assert_err;
+
+    #[test]
+    fn scopes_round_trip_through_their_string_form() {
+        for scope in [
+            Scope::SubscribersRead,
+            Scope::SubscribersWrite,
+            Scope::NewslettersPublish,
+            Scope::AuditRead,
+        ] {
+            assert_eq!(Scope::try_from(String::from(scope)), Ok(scope));
+        }
+        assert_err!(Scope::try_from("users:manage".to_string()));
+    }
+
+    #[test]
+    fn managing_users_and_keys_cannot_be_delegated_to_api_keys() {
+        assert_eq!(Scope::for_permission(Permission::ManageUsers), None);
+        assert_eq!(Scope::for_permission(Permission::ManageApiKeys), None);
+    }
+}
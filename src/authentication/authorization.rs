@@ -0,0 +1,248 @@
+use crate::authentication::{bearer_token, validate_api_key, AuthError, AuthenticatedUser, Scope};
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+
+/// Admin roles, from least to most privileged.
+/// Every role is granted the permissions of the roles before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Publisher,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Publisher => "publisher",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "publisher" => Ok(Self::Publisher),
+            "admin" => Ok(Self::Admin),
+            other => Err(format!(
+                "{other} is not a supported role. Use either `viewer`, `editor`, `publisher` or `admin`."
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    ReadSubscribers,
+    EditSubscribers,
+    PublishNewsletters,
+    ReadAuditLog,
+    ManageUsers,
+    ManageApiKeys,
+    ConfigureLogging,
+}
+
+impl Permission {
+    pub fn minimum_role(self) -> Role {
+        match self {
+            Permission::ReadSubscribers => Role::Viewer,
+            Permission::EditSubscribers => Role::Editor,
+            Permission::PublishNewsletters => Role::Publisher,
+            Permission::ReadAuditLog
+            | Permission::ManageUsers
+            | Permission::ManageApiKeys
+            | Permission::ConfigureLogging => Role::Admin,
+        }
+    }
+}
+
+impl AuthenticatedUser {
+    pub fn require(&self, permission: Permission) -> Result<(), AuthError> {
+        let minimum_role = permission.minimum_role();
+        if let Some(scopes) = &self.scopes {
+            let scope = Scope::for_permission(permission);
+            if !scope.is_some_and(|s| scopes.contains(&s)) {
+                tracing::warn!(
+                    username = %self.username,
+                    ?permission,
+                    "An API key was denied access"
+                );
+                return Err(AuthError::MissingScope(scope));
+            }
+        }
+        if self.role >= minimum_role {
+            Ok(())
+        } else {
+            tracing::warn!(
+                username = %self.username,
+                role = %self.role,
+                ?permission,
+                "An authenticated user was denied access"
+            );
+            Err(AuthError::Forbidden(minimum_role))
+        }
+    }
+}
+
+/// Type-level permission used to parametrise [`Authorized`].
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+pub struct CanReadSubscribers;
+pub struct CanEditSubscribers;
+pub struct CanPublishNewsletters;
+pub struct CanReadAuditLog;
+pub struct CanManageUsers;
+pub struct CanManageApiKeys;
+pub struct CanConfigureLogging;
+
+impl RequiredPermission for CanReadSubscribers {
+    const PERMISSION: Permission = Permission::ReadSubscribers;
+}
+
+impl RequiredPermission for CanEditSubscribers {
+    const PERMISSION: Permission = Permission::EditSubscribers;
+}
+
+impl RequiredPermission for CanPublishNewsletters {
+    const PERMISSION: Permission = Permission::PublishNewsletters;
+}
+
+impl RequiredPermission for CanReadAuditLog {
+    const PERMISSION: Permission = Permission::ReadAuditLog;
+}
+
+impl RequiredPermission for CanManageUsers {
+    const PERMISSION: Permission = Permission::ManageUsers;
+}
+
+impl RequiredPermission for CanManageApiKeys {
+    const PERMISSION: Permission = Permission::ManageApiKeys;
+}
+
+impl RequiredPermission for CanConfigureLogging {
+    const PERMISSION: Permission = Permission::ConfigureLogging;
+}
+
+/// Extracts an [`AuthenticatedUser`], or an API key sent as a 'Bearer' token,
+/// and rejects the request with a 403 unless their role (and scopes) grant `P`.
+pub struct Authorized<P> {
+    user: AuthenticatedUser,
+    permission: PhantomData<fn() -> P>,
+}
+
+impl<P> Authorized<P> {
+    pub fn into_inner(self) -> AuthenticatedUser {
+        self.user
+    }
+}
+
+impl<P> Deref for Authorized<P> {
+    type Target = AuthenticatedUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl<P: RequiredPermission + 'static> FromRequest for Authorized<P> {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let api_key = bearer_token(req.headers()).map(str::to_owned);
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let user = AuthenticatedUser::from_request(req, payload);
+        Box::pin(async move {
+            let user = match api_key {
+                Some(key) => {
+                    let pool = pool.context("The database pool is not registered as app data.")?;
+                    validate_api_key(&key, &pool).await?
+                }
+                None => user.await?,
+            };
+            user.require(P::PERMISSION)?;
+            Ok(Authorized {
+                user,
+                permission: PhantomData,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+    use crate::authentication::{AuthenticatedUser, Scope};
+    use claims::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    fn user(role: Role) -> AuthenticatedUser {
+        AuthenticatedUser {
+            user_id: Uuid::new_v4(),
+            username: "ursula".into(),
+            role,
+            scopes: None,
+        }
+    }
+
+    #[test]
+    fn higher_roles_inherit_the_permissions_of_lower_roles() {
+        assert_ok!(user(Role::Admin).require(Permission::PublishNewsletters));
+        assert_ok!(user(Role::Publisher).require(Permission::EditSubscribers));
+        assert_ok!(user(Role::Editor).require(Permission::ReadSubscribers));
+    }
+
+    #[test]
+    fn lower_roles_are_denied() {
+        assert_err!(user(Role::Viewer).require(Permission::EditSubscribers));
+        assert_err!(user(Role::Editor).require(Permission::PublishNewsletters));
+        assert_err!(user(Role::Publisher).require(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn api_keys_are_limited_to_their_scopes() {
+        let api_key = AuthenticatedUser {
+            scopes: Some(vec![Scope::SubscribersRead]),
+            ..user(Role::Admin)
+        };
+        assert_ok!(api_key.require(Permission::ReadSubscribers));
+        assert_err!(api_key.require(Permission::EditSubscribers));
+        assert_err!(api_key.require(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn api_keys_cannot_exceed_the_role_of_their_creator() {
+        let api_key = AuthenticatedUser {
+            scopes: Some(vec![Scope::NewslettersPublish]),
+            ..user(Role::Editor)
+        };
+        assert_err!(api_key.require(Permission::PublishNewsletters));
+    }
+
+    #[test]
+    fn roles_are_parsed_case_insensitively() {
+        assert_eq!(Role::try_from("Publisher".to_string()), Ok(Role::Publisher));
+        assert_err!(Role::try_from("owner".to_string()));
+    }
+}
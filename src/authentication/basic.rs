@@ -0,0 +1,99 @@
+use crate::authentication::{
+    get_session, two_factor_enabled, AuthError, Credentials, LoginThrottle, Role, Scope,
+};
+use crate::rate_limit::RateLimiter;
+use actix_web::dev::Payload;
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::{web, FromRequest, HttpRequest};
+use anyhow::Context;
+use base64::Engine;
+use secrecy::SecretBox;
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// An admin user authenticated either by a fully verified session or by
+/// 'Basic' credentials checked against the `users` table.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: Role,
+    /// Set when authenticated with an API key, which is limited to these
+    /// scopes on top of the role of its creator.
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .cloned()
+                .context("The database pool is not registered as app data.")?;
+            if !req.headers().contains_key(header::AUTHORIZATION) {
+                if let Some(session) = get_session(&req, &pool).await? {
+                    if !session.second_factor_pending {
+                        return Ok(session.user);
+                    }
+                }
+            }
+            let throttle = req
+                .app_data::<web::Data<LoginThrottle>>()
+                .cloned()
+                .context("The login throttle is not registered as app data.")?;
+            let credentials =
+                basic_authentication(req.headers()).map_err(AuthError::InvalidCredentials)?;
+            let client_ip = req
+                .app_data::<web::Data<RateLimiter>>()
+                .context("The rate limiter is not registered as app data.")?
+                .client_ip(&req);
+            let user = throttle
+                .validate_credentials(credentials, client_ip, &pool)
+                .await?;
+            // A password alone is not enough for accounts protected by a second factor.
+            if two_factor_enabled(&pool, user.user_id).await? {
+                return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                    "Two-factor authentication is enabled: log in to start a session instead."
+                )));
+            }
+            Ok(user)
+        })
+    }
+}
+
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing.")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: SecretBox::new(Box::new(password)),
+    })
+}
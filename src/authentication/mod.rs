@@ -0,0 +1,21 @@
+mod api_keys;
+mod authorization;
+mod basic;
+mod password;
+mod password_policy;
+mod session;
+mod throttle;
+mod tokens;
+mod two_factor;
+mod users;
+
+pub use api_keys::*;
+pub use authorization::*;
+pub use basic::*;
+pub use password::*;
+pub use password_policy::*;
+pub use session::*;
+pub use throttle::*;
+pub use tokens::*;
+pub use two_factor::*;
+pub use users::*;
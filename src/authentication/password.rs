@@ -0,0 +1,162 @@
+use crate::authentication::{AuthenticatedUser, Role, Scope};
+use crate::rate_limit::retry_after_seconds;
+use crate::telemetry::spawn_blocking_with_tracing;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use anyhow::Context;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use secrecy::{ExposeSecret, SecretBox};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretBox<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error("The `{0}` role is required to perform this action.")]
+    Forbidden(Role),
+    #[error("{}", missing_scope_message(*.0))]
+    MissingScope(Option<Scope>),
+    #[error("Too many failed login attempts. Try again later.")]
+    TooManyAttempts(std::time::Duration),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::InvalidCredentials(_) => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden(_) | AuthError::MissingScope(_) => StatusCode::FORBIDDEN,
+            AuthError::TooManyAttempts(_) => StatusCode::TOO_MANY_REQUESTS,
+            AuthError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            AuthError::InvalidCredentials(_) => {
+                let header_value = HeaderValue::from_str(r#"Basic realm="admin""#).unwrap();
+                response
+                    .insert_header((header::WWW_AUTHENTICATE, header_value))
+                    .finish()
+            }
+            AuthError::Forbidden(_) | AuthError::MissingScope(_) => response.body(self.to_string()),
+            AuthError::TooManyAttempts(retry_after) => response
+                .insert_header((header::RETRY_AFTER, retry_after_seconds(*retry_after)))
+                .body(self.to_string()),
+            AuthError::UnexpectedError(_) => response.finish(),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<AuthenticatedUser, AuthError> {
+    let mut user = None;
+    // Verify against a dummy hash when the user does not exist, so that the
+    // response time does not reveal which usernames are valid.
+    let mut expected_password_hash = SecretBox::new(Box::new(
+        "$argon2id$v=19$m=15000,t=2,p=1$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+            .to_string(),
+    ));
+
+    if let Some((stored_user_id, stored_password_hash, stored_role)) =
+        get_stored_credentials(&credentials.username, pool).await?
+    {
+        user = Some(AuthenticatedUser {
+            user_id: stored_user_id,
+            username: credentials.username,
+            role: stored_role,
+            scopes: None,
+        });
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task.")??;
+
+    user.ok_or_else(|| anyhow::anyhow!("Unknown username."))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+fn missing_scope_message(scope: Option<Scope>) -> String {
+    match scope {
+        Some(scope) => format!("The `{scope}` scope is required to perform this action."),
+        None => "API keys cannot perform this action.".to_string(),
+    }
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(Uuid, SecretBox<String>, Role)>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT user_id, password_hash, role FROM users WHERE username = $1",
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials.")?;
+    match row {
+        Some(row) => {
+            let role = Role::try_from(row.role).map_err(anyhow::Error::msg)?;
+            Ok(Some((
+                row.user_id,
+                SecretBox::new(Box::new(row.password_hash)),
+                role,
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+fn verify_password_hash(
+    expected_password_hash: SecretBox<String>,
+    password_candidate: SecretBox<String>,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+pub fn compute_password_hash(
+    password: SecretBox<String>,
+) -> Result<SecretBox<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)?
+    .to_string();
+    Ok(SecretBox::new(Box::new(password_hash)))
+}
@@ -0,0 +1,113 @@
+use secrecy::{ExposeSecret, SecretBox};
+use std::collections::HashSet;
+
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Common passwords taken from public breach corpora, one per line. Only the
+/// few long enough to pass the length rule are bundled: a full corpus is
+/// configured with `password_policy.breached_passwords_file`.
+const BUNDLED_BREACHED_PASSWORDS: &str = include_str!("breached_passwords.txt");
+
+/// The rules new passwords must follow.
+pub struct PasswordPolicy {
+    /// Lowercased, and only those long enough to pass the length rule.
+    breached_passwords: HashSet<String>,
+}
+
+impl Default for PasswordPolicy {
+    /// Checks against the bundled list of breached passwords only.
+    fn default() -> Self {
+        let mut policy = Self {
+            breached_passwords: HashSet::new(),
+        };
+        policy.extend_breached_passwords(BUNDLED_BREACHED_PASSWORDS);
+        policy
+    }
+}
+
+impl PasswordPolicy {
+    /// Adds breached passwords, one per line, on top of the bundled ones.
+    pub fn extend_breached_passwords(&mut self, list: &str) {
+        self.breached_passwords.extend(
+            list.lines()
+                .map(str::trim)
+                .filter(|l| l.chars().count() >= MIN_PASSWORD_LENGTH)
+                .map(str::to_lowercase),
+        );
+    }
+
+    pub fn validate(&self, username: &str, password: &SecretBox<String>) -> Result<(), String> {
+        let password = password.expose_secret();
+        let length = password.chars().count();
+        if length < MIN_PASSWORD_LENGTH {
+            return Err(format!(
+                "The password must be at least {MIN_PASSWORD_LENGTH} characters long."
+            ));
+        }
+        if length > MAX_PASSWORD_LENGTH {
+            return Err(format!(
+                "The password must be at most {MAX_PASSWORD_LENGTH} characters long."
+            ));
+        }
+        let lowercase_password = password.to_lowercase();
+        if !username.is_empty() && lowercase_password.contains(&username.to_lowercase()) {
+            return Err("The password must not contain the username.".into());
+        }
+        if self.breached_passwords.contains(&lowercase_password) {
+            return Err("The password appears in a list of breached passwords.".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PasswordPolicy;
+    use claims::{assert_err, assert_ok};
+    use secrecy::SecretBox;
+
+    fn secret(s: &str) -> SecretBox<String> {
+        SecretBox::new(Box::new(s.to_string()))
+    }
+
+    #[test]
+    fn a_long_uncommon_password_is_accepted() {
+        assert_ok!(
+            PasswordPolicy::default().validate("ursula", &secret("ansible-left-hand-of-darkness"))
+        );
+    }
+
+    #[test]
+    fn short_passwords_are_rejected() {
+        assert_err!(PasswordPolicy::default().validate("ursula", &secret("earthsea")));
+    }
+
+    #[test]
+    fn passwords_longer_than_128_characters_are_rejected() {
+        assert_err!(PasswordPolicy::default().validate("ursula", &secret(&"a".repeat(129))));
+    }
+
+    #[test]
+    fn passwords_containing_the_username_are_rejected() {
+        assert_err!(
+            PasswordPolicy::default().validate("ursula", &secret("my-name-is-Ursula-legit"))
+        );
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected_regardless_of_case() {
+        assert_err!(PasswordPolicy::default().validate("ursula", &secret("Password1234")));
+    }
+
+    #[test]
+    fn configured_breached_passwords_are_rejected_too() {
+        let password = secret("Tehanu-Tenar-Ged-1990");
+        let mut policy = PasswordPolicy::default();
+        assert_ok!(policy.validate("ursula", &password));
+
+        policy.extend_breached_passwords("short\ntehanu-tenar-ged-1990\n");
+
+        assert_err!(policy.validate("ursula", &password));
+    }
+}
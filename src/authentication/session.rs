@@ -0,0 +1,114 @@
+use crate::authentication::{generate_token, hash_token, AuthenticatedUser, Role};
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+pub const SESSION_COOKIE: &str = "session";
+const SESSION_TTL: Duration = Duration::hours(12);
+/// How long a user has to submit their second factor after their password.
+const PENDING_SESSION_TTL: Duration = Duration::minutes(5);
+
+/// A session looked up from the `session` cookie.
+pub struct Session {
+    pub token: String,
+    pub user: AuthenticatedUser,
+    pub second_factor_pending: bool,
+}
+
+/// Starts a session and returns the token to hand out as a cookie.
+#[tracing::instrument(name = "Create a session", skip(executor))]
+pub async fn create_session<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    second_factor_pending: bool,
+) -> Result<String, anyhow::Error> {
+    let token = generate_token();
+    let ttl = if second_factor_pending {
+        PENDING_SESSION_TTL
+    } else {
+        SESSION_TTL
+    };
+    sqlx::query!(
+        r#"INSERT INTO admin_sessions (token_hash, user_id, created_at, expires_at, second_factor_pending)
+        VALUES ($1, $2, $3, $4, $5)"#,
+        hash_token(&token),
+        user_id,
+        Utc::now(),
+        Utc::now() + ttl,
+        second_factor_pending,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store a new session.")?;
+    Ok(token)
+}
+
+#[tracing::instrument(name = "Delete a session", skip(executor, token))]
+pub async fn delete_session<'e>(
+    executor: impl PgExecutor<'e>,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM admin_sessions WHERE token_hash = $1",
+        hash_token(token)
+    )
+    .execute(executor)
+    .await
+    .context("Failed to delete a session.")?;
+    Ok(())
+}
+
+/// Returns the unexpired session identified by the request's cookie, if any.
+#[tracing::instrument(name = "Get session", skip(request, pool))]
+pub async fn get_session(
+    request: &HttpRequest,
+    pool: &PgPool,
+) -> Result<Option<Session>, anyhow::Error> {
+    let Some(token) = request
+        .cookie(SESSION_COOKIE)
+        .map(|c| c.value().to_string())
+    else {
+        return Ok(None);
+    };
+    let row = sqlx::query!(
+        r#"SELECT u.user_id, u.username, u.role, s.second_factor_pending
+        FROM admin_sessions s JOIN users u ON u.user_id = s.user_id
+        WHERE s.token_hash = $1 AND s.expires_at > now()"#,
+        hash_token(&token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch a session.")?;
+    row.map(|r| {
+        Ok(Session {
+            token,
+            user: AuthenticatedUser {
+                user_id: r.user_id,
+                username: r.username,
+                role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+                scopes: None,
+            },
+            second_factor_pending: r.second_factor_pending,
+        })
+    })
+    .transpose()
+}
+
+pub fn session_cookie(token: String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::seconds(SESSION_TTL.num_seconds()))
+        .finish()
+}
+
+pub fn expired_session_cookie() -> Cookie<'static> {
+    let mut cookie = session_cookie(String::new());
+    cookie.make_removal();
+    cookie
+}
@@ -0,0 +1,346 @@
+use crate::authentication::{validate_credentials, AuthError, AuthenticatedUser, Credentials};
+use crate::configuration::LoginProtectionSettings;
+use sqlx::PgPool;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Stale entries are only swept once the map grows past this size.
+const SWEEP_THRESHOLD: usize = 10_000;
+/// How long to wait when the attempts still being verified use up the failures
+/// left before a lockout.
+const PENDING_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ThrottleKey {
+    Username(String),
+    Ip(IpAddr),
+}
+
+#[derive(Default)]
+struct Attempts {
+    failures: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+    /// Attempts whose credentials are still being verified.
+    pending: usize,
+}
+
+impl Attempts {
+    /// Forgets failures that slid out of the window and expired lockouts.
+    fn prune(&mut self, now: Instant, window: Duration) {
+        while self
+            .failures
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            self.failures.pop_front();
+        }
+        if self.locked_until.is_some_and(|t| t <= now) {
+            self.locked_until = None;
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.failures.is_empty() && self.locked_until.is_none() && self.pending == 0
+    }
+}
+
+/// Tracks failed logins per username and per client IP over a sliding window.
+/// Every recent failure slows down the next attempt, and too many of them lock
+/// the username or IP out for a while.
+pub struct LoginThrottle {
+    settings: LoginProtectionSettings,
+    attempts: Mutex<HashMap<ThrottleKey, Attempts>>,
+}
+
+impl LoginThrottle {
+    pub fn new(settings: LoginProtectionSettings) -> Self {
+        Self {
+            settings,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn keys(username: &str, client_ip: Option<IpAddr>) -> Vec<ThrottleKey> {
+        let mut keys = vec![ThrottleKey::Username(username.to_lowercase())];
+        keys.extend(client_ip.map(ThrottleKey::Ip));
+        keys
+    }
+
+    fn max_failures(&self, key: &ThrottleKey) -> usize {
+        match key {
+            ThrottleKey::Username(_) => self.settings.max_failures_per_username,
+            ThrottleKey::Ip(_) => self.settings.max_failures_per_ip,
+        }
+    }
+
+    #[cfg(test)]
+    fn check(&self, username: &str, client_ip: Option<IpAddr>) -> Result<Duration, Duration> {
+        let mut attempts = self.attempts.lock().unwrap();
+        self.check_attempts(&mut attempts, username, client_ip)
+    }
+
+    /// Returns how long to delay this attempt, or how long until the lockout ends.
+    /// Pending attempts count as failures, as they may turn out to be.
+    fn check_attempts(
+        &self,
+        attempts: &mut HashMap<ThrottleKey, Attempts>,
+        username: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<Duration, Duration> {
+        let now = Instant::now();
+        let mut failures = 0;
+        for key in Self::keys(username, client_ip) {
+            let Some(entry) = attempts.get_mut(&key) else {
+                continue;
+            };
+            entry.prune(now, self.settings.window());
+            if let Some(locked_until) = entry.locked_until {
+                return Err(locked_until - now);
+            }
+            if entry.failures.len() + entry.pending >= self.max_failures(&key) {
+                return Err(PENDING_RETRY_AFTER);
+            }
+            failures = failures.max(entry.failures.len() + entry.pending);
+        }
+        Ok(self.settings.delay_for(failures))
+    }
+
+    /// Checks an attempt and counts it as pending in the same critical section,
+    /// so that concurrent attempts cannot all get in before any of them fails.
+    fn begin_attempt(
+        &self,
+        username: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<(PendingAttempt<'_>, Duration), Duration> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let delay = self.check_attempts(&mut attempts, username, client_ip)?;
+        for key in Self::keys(username, client_ip) {
+            attempts.entry(key).or_default().pending += 1;
+        }
+        let attempt = PendingAttempt {
+            throttle: self,
+            username: username.to_string(),
+            client_ip,
+        };
+        Ok((attempt, delay))
+    }
+
+    fn end_attempt(&self, username: &str, client_ip: Option<IpAddr>) {
+        let mut attempts = self.attempts.lock().unwrap();
+        for key in Self::keys(username, client_ip) {
+            if let Some(entry) = attempts.get_mut(&key) {
+                entry.pending = entry.pending.saturating_sub(1);
+            }
+        }
+    }
+
+    fn record_failure(&self, username: &str, client_ip: Option<IpAddr>) {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+        if attempts.len() > SWEEP_THRESHOLD {
+            let window = self.settings.window();
+            attempts.retain(|_, entry| {
+                entry.prune(now, window);
+                !entry.is_stale()
+            });
+        }
+        for key in Self::keys(username, client_ip) {
+            let max_failures = self.max_failures(&key);
+            let entry = attempts.entry(key.clone()).or_default();
+            entry.prune(now, self.settings.window());
+            entry.failures.push_back(now);
+            if entry.failures.len() >= max_failures {
+                entry.failures.clear();
+                entry.locked_until = Some(now + self.settings.lockout());
+                tracing::warn!(
+                    target: "security",
+                    username,
+                    client_ip = ?client_ip,
+                    locked_out = ?key,
+                    lockout_seconds = self.settings.lockout_seconds,
+                    "Too many failed logins: temporarily locking out"
+                );
+            }
+        }
+    }
+
+    /// A successful login clears the username's failures, but not the IP's:
+    /// otherwise an attacker could reset their counter with their own account.
+    fn record_success(&self, username: &str) {
+        self.attempts
+            .lock()
+            .unwrap()
+            .remove(&ThrottleKey::Username(username.to_lowercase()));
+    }
+
+    /// [`validate_credentials`], guarded against brute-force attacks.
+    #[tracing::instrument(
+        name = "Validate credentials with brute-force protection",
+        skip(self, credentials, pool),
+        fields(username = %credentials.username)
+    )]
+    pub async fn validate_credentials(
+        &self,
+        credentials: Credentials,
+        client_ip: Option<IpAddr>,
+        pool: &PgPool,
+    ) -> Result<AuthenticatedUser, AuthError> {
+        let username = credentials.username.clone();
+        let attempt = match self.begin_attempt(&username, client_ip) {
+            Ok((attempt, delay)) => {
+                if !delay.is_zero() {
+                    tracing::info!(
+                        target: "security",
+                        delay_milliseconds = delay.as_millis() as u64,
+                        "Delaying a login attempt after recent failures"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                attempt
+            }
+            Err(retry_after) => {
+                tracing::warn!(
+                    target: "security",
+                    client_ip = ?client_ip,
+                    "Rejected a login attempt over the failure limit"
+                );
+                return Err(AuthError::TooManyAttempts(retry_after));
+            }
+        };
+
+        let result = validate_credentials(credentials, pool).await;
+        match &result {
+            Ok(_) => attempt.succeed(),
+            Err(AuthError::InvalidCredentials(_)) => {
+                tracing::warn!(
+                    target: "security",
+                    client_ip = ?client_ip,
+                    "Failed login attempt"
+                );
+                attempt.fail();
+            }
+            // Dropping the attempt releases it without counting it.
+            Err(_) => {}
+        }
+        result
+    }
+}
+
+/// A login attempt counted as pending until it is dropped, decided or not:
+/// an attempt whose client went away is not counted as a failure.
+struct PendingAttempt<'a> {
+    throttle: &'a LoginThrottle,
+    username: String,
+    client_ip: Option<IpAddr>,
+}
+
+impl PendingAttempt<'_> {
+    fn succeed(self) {
+        self.throttle.record_success(&self.username);
+    }
+
+    /// The failure is recorded before the attempt stops being pending, so that
+    /// it is never left uncounted in between.
+    fn fail(self) {
+        self.throttle.record_failure(&self.username, self.client_ip);
+    }
+}
+
+impl Drop for PendingAttempt<'_> {
+    fn drop(&mut self) {
+        self.throttle.end_attempt(&self.username, self.client_ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LoginThrottle;
+    use crate::configuration::LoginProtectionSettings;
+    use claims::{assert_err, assert_ok};
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    const IP: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle::new(LoginProtectionSettings {
+            window_seconds: 60,
+            max_failures_per_username: 3,
+            max_failures_per_ip: 5,
+            lockout_seconds: 60,
+            delay_step_milliseconds: 100,
+            max_delay_milliseconds: 150,
+        })
+    }
+
+    #[test]
+    fn delays_grow_with_failures_up_to_the_maximum() {
+        let throttle = throttle();
+        assert_eq!(throttle.check("ursula", IP), Ok(Duration::ZERO));
+        throttle.record_failure("ursula", IP);
+        assert_eq!(throttle.check("ursula", IP), Ok(Duration::from_millis(100)));
+        throttle.record_failure("ursula", IP);
+        assert_eq!(throttle.check("ursula", IP), Ok(Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn usernames_are_locked_out_after_too_many_failures() {
+        let throttle = throttle();
+        for _ in 0..3 {
+            throttle.record_failure("Ursula", None);
+        }
+        assert_err!(throttle.check("ursula", None));
+        assert_ok!(throttle.check("mallory", None));
+    }
+
+    #[test]
+    fn ips_are_locked_out_across_usernames() {
+        let throttle = throttle();
+        for i in 0..5 {
+            throttle.record_failure(&format!("user-{i}"), IP);
+        }
+        assert_err!(throttle.check("another-user", IP));
+        assert_ok!(throttle.check("another-user", None));
+    }
+
+    #[test]
+    fn a_success_clears_the_username_but_not_the_ip() {
+        let throttle = throttle();
+        for _ in 0..2 {
+            throttle.record_failure("ursula", IP);
+        }
+        throttle.record_success("ursula");
+        assert_eq!(throttle.check("ursula", None), Ok(Duration::ZERO));
+        assert_eq!(throttle.check("ursula", IP), Ok(Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn concurrent_attempts_cannot_get_past_the_lockout_threshold() {
+        let throttle = throttle();
+        let attempts: Vec<_> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..20)
+                .map(|_| s.spawn(|| throttle.begin_attempt("ursula", IP).ok()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let admitted: Vec<_> = attempts.into_iter().flatten().collect();
+
+        assert_eq!(admitted.len(), 3);
+        for (attempt, _) in admitted {
+            attempt.fail();
+        }
+        assert_err!(throttle.check("ursula", None));
+    }
+
+    #[test]
+    fn undecided_attempts_are_released() {
+        let throttle = throttle();
+        for _ in 0..5 {
+            let (attempt, _) = assert_ok!(throttle.begin_attempt("ursula", IP));
+            drop(attempt);
+        }
+        assert_eq!(throttle.check("ursula", IP), Ok(Duration::ZERO));
+    }
+}
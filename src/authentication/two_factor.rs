@@ -0,0 +1,359 @@
+use crate::authentication::{generate_token, hash_token};
+use crate::rate_limit::retry_after_seconds;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+const TOTP_ISSUER: &str = "zero2prod";
+const TOTP_STEP_SECONDS: u64 = 30;
+/// Failed codes allowed before the account is temporarily locked.
+const MAX_FAILED_CODES: i32 = 5;
+const LOCKOUT_DURATION: Duration = Duration::minutes(15);
+const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TwoFactorError {
+    #[error("The code is invalid.")]
+    InvalidCode,
+    #[error("Too many invalid codes. Try again after {0}.")]
+    LockedOut(DateTime<Utc>),
+    #[error("Two-factor authentication is already enabled.")]
+    AlreadyEnabled,
+    #[error("There is no pending two-factor enrolment to confirm.")]
+    NotEnrolled,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for TwoFactorError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TwoFactorError::InvalidCode => StatusCode::UNAUTHORIZED,
+            TwoFactorError::LockedOut(_) => StatusCode::TOO_MANY_REQUESTS,
+            TwoFactorError::AlreadyEnabled | TwoFactorError::NotEnrolled => StatusCode::BAD_REQUEST,
+            TwoFactorError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            TwoFactorError::LockedOut(until) => {
+                let retry_after = (*until - Utc::now())
+                    .to_std()
+                    .map_or(1, retry_after_seconds);
+                response
+                    .insert_header((header::RETRY_AFTER, retry_after))
+                    .body(self.to_string())
+            }
+            TwoFactorError::UnexpectedError(_) => response.finish(),
+            _ => response.body(self.to_string()),
+        }
+    }
+}
+
+/// What an authenticator app needs to be set up, shown once during enrolment.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub qr_code_svg: String,
+}
+
+/// A second factor submitted at login: either a TOTP code or a recovery code.
+pub enum SecondFactor {
+    Totp(String),
+    RecoveryCode(String),
+}
+
+fn totp(secret: &str, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {e:?}"))?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        TOTP_STEP_SECONDS,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        username.to_string(),
+    )
+    .context("Failed to build a TOTP generator.")
+}
+
+/// Returns the time step matched by `code`, if any.
+/// One step of clock skew is tolerated in either direction.
+fn matching_step(totp: &TOTP, code: &str) -> Option<i64> {
+    let now = Utc::now().timestamp() as u64 / TOTP_STEP_SECONDS;
+    (now.saturating_sub(1)..=now + 1)
+        .find(|step| totp.generate(step * TOTP_STEP_SECONDS) == code)
+        .map(|step| step as i64)
+}
+
+#[tracing::instrument(
+    name = "Check whether two-factor authentication is enabled",
+    skip(pool)
+)]
+pub async fn two_factor_enabled(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let enabled = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM user_totp WHERE user_id = $1 AND enabled_at IS NOT NULL
+        ) AS "enabled!""#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether two-factor authentication is enabled.")?;
+    Ok(enabled)
+}
+
+/// Generates a new TOTP secret for the user, replacing any unconfirmed one.
+#[tracing::instrument(name = "Start TOTP enrolment", skip(pool))]
+pub async fn start_totp_enrollment(
+    pool: &PgPool,
+    user_id: Uuid,
+    username: &str,
+) -> Result<TotpEnrollment, TwoFactorError> {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let totp = totp(&secret, username)?;
+    let stored = sqlx::query!(
+        r#"INSERT INTO user_totp (user_id, secret, created_at) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id) DO UPDATE
+        SET secret = EXCLUDED.secret, created_at = EXCLUDED.created_at
+        WHERE user_totp.enabled_at IS NULL"#,
+        user_id,
+        secret,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    if stored.rows_affected() == 0 {
+        return Err(TwoFactorError::AlreadyEnabled);
+    }
+
+    let otpauth_uri = totp.get_url();
+    let qr_code_svg = QrCode::new(otpauth_uri.as_bytes())
+        .context("Failed to encode the otpauth URI as a QR code.")?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Ok(TotpEnrollment {
+        secret,
+        otpauth_uri,
+        qr_code_svg,
+    })
+}
+
+/// Enables two-factor authentication once the user proved their authenticator
+/// works, and returns freshly generated recovery codes. They are only ever
+/// shown here: the database keeps their hashes.
+#[tracing::instrument(name = "Confirm TOTP enrolment", skip(transaction, code))]
+pub async fn confirm_totp_enrollment(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    username: &str,
+    code: &str,
+) -> Result<Vec<String>, TwoFactorError> {
+    let secret = sqlx::query_scalar!(
+        "SELECT secret FROM user_totp WHERE user_id = $1 AND enabled_at IS NULL FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to fetch the pending TOTP secret.")?
+    .ok_or(TwoFactorError::NotEnrolled)?;
+    let step =
+        matching_step(&totp(&secret, username)?, code.trim()).ok_or(TwoFactorError::InvalidCode)?;
+    transaction
+        .execute(sqlx::query!(
+            "UPDATE user_totp SET enabled_at = $1, last_used_step = $2 WHERE user_id = $3",
+            Utc::now(),
+            step,
+            user_id
+        ))
+        .await
+        .context("Failed to enable two-factor authentication.")?;
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|c| hash_token(&normalize_recovery_code(c)))
+        .collect();
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM user_recovery_codes WHERE user_id = $1",
+            user_id
+        ))
+        .await
+        .context("Failed to delete old recovery codes.")?;
+    transaction
+        .execute(sqlx::query!(
+            r#"INSERT INTO user_recovery_codes (user_id, code_hash)
+            SELECT $1, * FROM UNNEST($2::TEXT[])"#,
+            user_id,
+            &code_hashes,
+        ))
+        .await
+        .context("Failed to store recovery codes.")?;
+    Ok(recovery_codes)
+}
+
+/// Checks the second factor of a login, locking the account for a while after
+/// [`MAX_FAILED_CODES`] consecutive failures.
+#[tracing::instrument(name = "Verify second factor", skip(pool, second_factor))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    username: &str,
+    second_factor: SecondFactor,
+) -> Result<(), TwoFactorError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let state = sqlx::query!(
+        r#"SELECT secret, last_used_step, failed_attempts, locked_until FROM user_totp
+        WHERE user_id = $1 AND enabled_at IS NOT NULL FOR UPDATE"#,
+        user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to fetch the TOTP state.")?
+    .ok_or(TwoFactorError::NotEnrolled)?;
+    if let Some(locked_until) = state.locked_until.filter(|t| *t > Utc::now()) {
+        return Err(TwoFactorError::LockedOut(locked_until));
+    }
+
+    let verified = match second_factor {
+        SecondFactor::Totp(code) => {
+            let step = matching_step(&totp(&state.secret, username)?, code.trim())
+                // A code cannot be replayed, even within its validity window.
+                .filter(|step| state.last_used_step.is_none_or(|last| *step > last));
+            if let Some(step) = step {
+                transaction
+                    .execute(sqlx::query!(
+                        "UPDATE user_totp SET last_used_step = $1 WHERE user_id = $2",
+                        step,
+                        user_id
+                    ))
+                    .await
+                    .context("Failed to record the used TOTP step.")?;
+            }
+            step.is_some()
+        }
+        SecondFactor::RecoveryCode(code) => {
+            transaction
+                .execute(sqlx::query!(
+                    r#"UPDATE user_recovery_codes SET used_at = $1
+                    WHERE user_id = $2 AND code_hash = $3 AND used_at IS NULL"#,
+                    Utc::now(),
+                    user_id,
+                    hash_token(&normalize_recovery_code(&code)),
+                ))
+                .await
+                .context("Failed to consume the recovery code.")?
+                .rows_affected()
+                == 1
+        }
+    };
+
+    let result = if verified {
+        transaction
+            .execute(sqlx::query!(
+                "UPDATE user_totp SET failed_attempts = 0, locked_until = NULL WHERE user_id = $1",
+                user_id
+            ))
+            .await
+            .context("Failed to reset the failed attempts counter.")?;
+        Ok(())
+    } else {
+        let failed_attempts = state.failed_attempts + 1;
+        let locked_until =
+            (failed_attempts >= MAX_FAILED_CODES).then(|| Utc::now() + LOCKOUT_DURATION);
+        tracing::warn!(
+            %user_id,
+            failed_attempts,
+            locked = locked_until.is_some(),
+            "An invalid second factor was submitted"
+        );
+        transaction
+            .execute(sqlx::query!(
+                r#"UPDATE user_totp SET failed_attempts = $1, locked_until = $2
+                WHERE user_id = $3"#,
+                if locked_until.is_some() {
+                    0
+                } else {
+                    failed_attempts
+                },
+                locked_until,
+                user_id
+            ))
+            .await
+            .context("Failed to record the failed attempt.")?;
+        Err(locked_until.map_or(TwoFactorError::InvalidCode, TwoFactorError::LockedOut))
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to verify a second factor.")?;
+    result
+}
+
+fn generate_recovery_code() -> String {
+    let code = generate_token().to_lowercase();
+    format!("{}-{}", &code[..5], &code[5..10])
+}
+
+/// Recovery codes are accepted regardless of case, dashes and spaces.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matching_step, normalize_recovery_code, totp, TOTP_STEP_SECONDS};
+    use chrono::Utc;
+    use totp_rs::Secret;
+
+    fn secret() -> String {
+        Secret::generate_secret().to_encoded().to_string()
+    }
+
+    #[test]
+    fn the_current_code_matches_the_current_step() {
+        let totp = totp(&secret(), "ursula").unwrap();
+        let code = totp.generate_current().unwrap();
+        let step = Utc::now().timestamp() / TOTP_STEP_SECONDS as i64;
+        assert!(matching_step(&totp, &code).is_some_and(|s| (s - step).abs() <= 1));
+    }
+
+    #[test]
+    fn codes_from_other_secrets_do_not_match() {
+        let code = totp(&secret(), "ursula")
+            .unwrap()
+            .generate_current()
+            .unwrap();
+        assert_eq!(
+            matching_step(&totp(&secret(), "ursula").unwrap(), &code),
+            None
+        );
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        assert_eq!(normalize_recovery_code(" AbCdE-12345 "), "abcde12345");
+    }
+}
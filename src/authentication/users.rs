@@ -0,0 +1,87 @@
+use crate::authentication::{compute_password_hash, Role};
+use crate::redaction::Redacted;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use chrono::Utc;
+use secrecy::{ExposeSecret, SecretBox};
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CreateUserError {
+    #[error("A user with this username or email already exists.")]
+    AlreadyExists,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[tracing::instrument(
+    name = "Create a user",
+    skip(executor, password, email),
+    fields(email = ?email.map(Redacted))
+)]
+pub async fn create_user<'e>(
+    executor: impl PgExecutor<'e>,
+    username: &str,
+    password: SecretBox<String>,
+    role: Role,
+    email: Option<&str>,
+) -> Result<Uuid, CreateUserError> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO users (user_id, username, password_hash, role, email)
+        VALUES ($1, $2, $3, $4, $5)"#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        role.as_str(),
+        email,
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => CreateUserError::AlreadyExists,
+        _ => CreateUserError::UnexpectedError(
+            anyhow::Error::new(e).context("Failed to insert a new user in the database."),
+        ),
+    })?;
+    Ok(user_id)
+}
+
+/// Replaces the password of the user and revokes what the previous one gave
+/// access to: their sessions and their pending password reset links.
+#[tracing::instrument(name = "Change password", skip(connection, password))]
+pub async fn change_password(
+    connection: &mut PgConnection,
+    user_id: Uuid,
+    password: SecretBox<String>,
+) -> Result<(), anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        password_hash.expose_secret(),
+        user_id
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to change user's password in the database.")?;
+    sqlx::query!("DELETE FROM admin_sessions WHERE user_id = $1", user_id)
+        .execute(&mut *connection)
+        .await
+        .context("Failed to revoke the sessions of the user.")?;
+    sqlx::query!(
+        r#"UPDATE password_reset_tokens SET used_at = $1
+        WHERE user_id = $2 AND used_at IS NULL"#,
+        Utc::now(),
+        user_id
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to revoke the password reset links of the user.")?;
+    Ok(())
+}
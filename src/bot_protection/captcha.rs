@@ -0,0 +1,153 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretBox};
+
+/// Checks the response produced by the CAPTCHA widget of the subscription form.
+pub trait CaptchaVerifier: Send + Sync {
+    /// Whether `response` proves that a person filled in the form.
+    fn verify<'a>(
+        &'a self,
+        response: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool, anyhow::Error>>;
+}
+
+/// Accepts everything, for deployments without a CAPTCHA.
+pub struct NoCaptcha;
+
+impl CaptchaVerifier for NoCaptcha {
+    fn verify<'a>(
+        &'a self,
+        _response: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        async { Ok(true) }.boxed()
+    }
+}
+
+/// Verifies responses against a `siteverify` endpoint: the response and our
+/// secret are POSTed as a form, and the provider answers `{"success": bool}`.
+pub struct HttpCaptchaVerifier {
+    http_client: Client,
+    verify_url: String,
+    secret: SecretBox<String>,
+}
+
+#[derive(serde::Serialize)]
+struct SiteVerifyRequest<'a> {
+    secret: &'a str,
+    response: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+impl HttpCaptchaVerifier {
+    pub fn new(verify_url: &str, secret: SecretBox<String>, timeout: std::time::Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            verify_url: verify_url.to_string(),
+            secret,
+        }
+    }
+
+    async fn site_verify(&self, response: &str) -> Result<bool, anyhow::Error> {
+        let request_body = SiteVerifyRequest {
+            secret: self.secret.expose_secret(),
+            response,
+        };
+        let body: SiteVerifyResponse = self
+            .http_client
+            .post(&self.verify_url)
+            .form(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(body.success)
+    }
+}
+
+impl CaptchaVerifier for HttpCaptchaVerifier {
+    fn verify<'a>(
+        &'a self,
+        response: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        async move {
+            match response.filter(|r| !r.is_empty()) {
+                Some(response) => self.site_verify(response).await,
+                None => Ok(false),
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaptchaVerifier, HttpCaptchaVerifier};
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::SecretBox;
+    use std::time::Duration;
+    use wiremock::matchers::{body_string_contains, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn verifier(server: &MockServer) -> HttpCaptchaVerifier {
+        HttpCaptchaVerifier::new(
+            &server.uri(),
+            SecretBox::new(Box::new("captcha-secret".to_string())),
+            Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn the_provider_decides_whether_a_response_is_valid() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("secret=captcha-secret"))
+            .and(body_string_contains("response=human"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("response=bot"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "error-codes": ["invalid-input-response"]
+            })))
+            .mount(&server)
+            .await;
+
+        assert_ok_eq!(verifier(&server).verify(Some("human")).await, true);
+        assert_ok_eq!(verifier(&server).verify(Some("bot")).await, false);
+    }
+
+    #[tokio::test]
+    async fn a_missing_response_is_rejected_without_asking_the_provider() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        assert_ok_eq!(verifier(&server).verify(None).await, false);
+        assert_ok_eq!(verifier(&server).verify(Some("")).await, false);
+    }
+
+    #[tokio::test]
+    async fn an_unavailable_provider_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        assert_err!(verifier(&server).verify(Some("human")).await);
+    }
+}
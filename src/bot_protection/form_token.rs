@@ -0,0 +1,123 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretBox};
+use sha2::Sha256;
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum FormTokenError {
+    #[error("The form token is invalid.")]
+    Invalid,
+    #[error("The form was submitted too quickly.")]
+    TooFast,
+    #[error("The form has expired. Reload the page and try again.")]
+    Expired,
+    #[error("The form was already submitted. Reload the page and try again.")]
+    Reused,
+}
+
+/// Signs the time a form was rendered at, so that its submission can tell how
+/// long it took to fill in without the client being able to lie about it.
+/// Each token also carries a random nonce, so that its use can be recorded.
+pub struct FormTokenSigner {
+    secret: SecretBox<String>,
+}
+
+impl FormTokenSigner {
+    pub fn new(secret: SecretBox<String>) -> Self {
+        Self { secret }
+    }
+
+    fn mac(&self, issued_at: i64, nonce: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size.");
+        mac.update(format!("{issued_at}.{nonce}").as_bytes());
+        mac
+    }
+
+    /// A token of the form `<unix timestamp>.<nonce>.<hex HMAC-SHA256 of both>`.
+    pub fn issue(&self, issued_at: i64, nonce: &str) -> String {
+        let signature = self.mac(issued_at, nonce).finalize().into_bytes();
+        format!("{issued_at}.{nonce}.{}", hex::encode(signature))
+    }
+
+    /// Checks that `token` was issued by us between `max_age` and `min_age`
+    /// before `now`, and returns its nonce.
+    pub fn verify<'a>(
+        &self,
+        token: &'a str,
+        now: i64,
+        min_age: Duration,
+        max_age: Duration,
+    ) -> Result<&'a str, FormTokenError> {
+        let mut parts = token.split('.');
+        let (Some(issued_at), Some(nonce), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(FormTokenError::Invalid);
+        };
+        let issued_at: i64 = issued_at.parse().map_err(|_| FormTokenError::Invalid)?;
+        let signature = hex::decode(signature).map_err(|_| FormTokenError::Invalid)?;
+        self.mac(issued_at, nonce)
+            .verify_slice(&signature)
+            .map_err(|_| FormTokenError::Invalid)?;
+
+        let age = u64::try_from(now - issued_at).map_err(|_| FormTokenError::Invalid)?;
+        if age < min_age.as_secs() {
+            return Err(FormTokenError::TooFast);
+        }
+        if age > max_age.as_secs() {
+            return Err(FormTokenError::Expired);
+        }
+        Ok(nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FormTokenError, FormTokenSigner};
+    use claims::assert_ok;
+    use secrecy::SecretBox;
+    use std::time::Duration;
+
+    const MIN_AGE: Duration = Duration::from_secs(3);
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    fn signer(secret: &str) -> FormTokenSigner {
+        FormTokenSigner::new(SecretBox::new(Box::new(secret.to_string())))
+    }
+
+    #[test]
+    fn a_token_is_accepted_between_its_minimum_and_maximum_age() {
+        let token = signer("secret").issue(1_000, "nonce");
+        let nonce = assert_ok!(signer("secret").verify(&token, 1_010, MIN_AGE, MAX_AGE));
+        assert_eq!(nonce, "nonce");
+    }
+
+    #[test]
+    fn a_token_is_rejected_when_used_too_early_or_too_late() {
+        let token = signer("secret").issue(1_000, "nonce");
+        let verify = |now| signer("secret").verify(&token, now, MIN_AGE, MAX_AGE);
+        assert_eq!(verify(1_001), Err(FormTokenError::TooFast));
+        assert_eq!(verify(1_061), Err(FormTokenError::Expired));
+        assert_eq!(verify(999), Err(FormTokenError::Invalid));
+    }
+
+    #[test]
+    fn a_forged_token_is_rejected() {
+        let forged = signer("another secret").issue(1_000, "nonce");
+        let backdated = signer("secret")
+            .issue(1_000, "nonce")
+            .replacen("1000", "900", 1);
+        let renewed = signer("secret")
+            .issue(1_000, "nonce")
+            .replacen("nonce", "other", 1);
+        for token in [forged, backdated, renewed, "garbage".to_string()] {
+            assert_eq!(
+                signer("secret").verify(&token, 1_010, MIN_AGE, MAX_AGE),
+                Err(FormTokenError::Invalid)
+            );
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::bot_protection::{CaptchaVerifier, FormTokenError, FormTokenSigner};
+use crate::configuration::BotProtectionSettings;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, SecretBox};
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+
+/// How often the nonces of expired form tokens are deleted.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, thiserror::Error)]
+pub enum BotCheckError {
+    #[error("The honeypot field was filled in.")]
+    HoneypotFilled,
+    #[error(transparent)]
+    FormToken(#[from] FormTokenError),
+    #[error("The CAPTCHA was not solved.")]
+    CaptchaFailed,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// The parts of a form submission that tell people from bots.
+pub struct BotSignals<'a> {
+    /// A field hidden from people, which only bots fill in.
+    pub honeypot: Option<&'a str>,
+    pub form_token: Option<&'a str>,
+    pub captcha_response: Option<&'a str>,
+}
+
+pub struct BotProtection {
+    form_tokens: FormTokenSigner,
+    require_form_token: bool,
+    min_fill_time: Duration,
+    max_form_age: Duration,
+    captcha: Box<dyn CaptchaVerifier>,
+    /// Where the nonces of used form tokens are recorded.
+    db_pool: PgPool,
+}
+
+impl BotProtection {
+    pub fn new(settings: &BotProtectionSettings, db_pool: PgPool) -> Self {
+        let secret = settings.form_token_secret.expose_secret().clone();
+        Self {
+            form_tokens: FormTokenSigner::new(SecretBox::new(Box::new(secret))),
+            require_form_token: settings.require_form_token,
+            min_fill_time: settings.min_fill_time(),
+            max_form_age: settings.max_form_age(),
+            captcha: settings.captcha.verifier(),
+            db_pool,
+        }
+    }
+
+    /// A token to embed in the form when it is rendered.
+    pub fn issue_form_token(&self) -> String {
+        let mut rng = thread_rng();
+        let nonce: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(22)
+            .collect();
+        self.form_tokens.issue(Utc::now().timestamp(), &nonce)
+    }
+
+    /// Cheap checks come first, so that bots rarely cost us a CAPTCHA verification.
+    /// The form token that passed, if any, is only used up once the submission
+    /// is stored: see [`FormTokenUse::record`].
+    #[tracing::instrument(name = "Check a submission for bots", skip(self, signals))]
+    pub async fn check(
+        &self,
+        signals: BotSignals<'_>,
+    ) -> Result<Option<FormTokenUse>, BotCheckError> {
+        if signals.honeypot.is_some_and(|value| !value.is_empty()) {
+            return Err(BotCheckError::HoneypotFilled);
+        }
+        let token_use = match signals.form_token {
+            Some(token) => {
+                let nonce = self.form_tokens.verify(
+                    token,
+                    Utc::now().timestamp(),
+                    self.min_fill_time,
+                    self.max_form_age,
+                )?;
+                Some(self.unused(nonce).await?)
+            }
+            None if self.require_form_token => return Err(FormTokenError::Invalid.into()),
+            // Clients predating the form token keep working until it is required.
+            None => None,
+        };
+        if !self.captcha.verify(signals.captcha_response).await? {
+            return Err(BotCheckError::CaptchaFailed);
+        }
+        Ok(token_use)
+    }
+
+    /// Fails if the token carrying `nonce` was already used: a token is good for one submission.
+    async fn unused(&self, nonce: &str) -> Result<FormTokenUse, BotCheckError> {
+        let used = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM used_form_tokens WHERE nonce = $1) AS "used!""#,
+            nonce
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .context("Failed to look up the use of a form token.")?;
+        if used {
+            return Err(FormTokenError::Reused.into());
+        }
+        let expires_at = chrono::Duration::from_std(self.max_form_age)
+            .ok()
+            .and_then(|age| Utc::now().checked_add_signed(age))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        Ok(FormTokenUse {
+            nonce: nonce.to_string(),
+            expires_at,
+        })
+    }
+}
+
+/// A form token that passed the checks, still to be used up by the submission.
+#[derive(Debug)]
+pub struct FormTokenUse {
+    nonce: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl FormTokenUse {
+    /// Records the use of the token in the transaction storing the submission,
+    /// so that a submission which is rejected further on does not use it up.
+    /// Fails if a concurrent submission used the token first.
+    pub async fn record(self, connection: &mut PgConnection) -> Result<(), BotCheckError> {
+        let recorded = sqlx::query!(
+            r#"INSERT INTO used_form_tokens (nonce, expires_at) VALUES ($1, $2)
+            ON CONFLICT (nonce) DO NOTHING"#,
+            self.nonce,
+            self.expires_at,
+        )
+        .execute(connection)
+        .await
+        .context("Failed to record the use of a form token.")?
+        .rows_affected();
+        if recorded == 0 {
+            return Err(FormTokenError::Reused.into());
+        }
+        Ok(())
+    }
+}
+
+/// Deletes the nonces of form tokens that expired every [`SWEEP_INTERVAL`]:
+/// those tokens are refused anyway. Runs until the runtime shuts down.
+pub async fn sweep_used_form_tokens(db_pool: PgPool) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = delete_expired_form_tokens(&db_pool).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to sweep the used form tokens");
+        }
+    }
+}
+
+#[tracing::instrument(name = "Sweep used form tokens", skip(db_pool))]
+async fn delete_expired_form_tokens(db_pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!("DELETE FROM used_form_tokens WHERE expires_at <= now()")
+        .execute(db_pool)
+        .await
+        .context("Failed to delete expired form tokens.")?;
+    Ok(())
+}
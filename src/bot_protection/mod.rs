@@ -0,0 +1,7 @@
+mod captcha;
+mod form_token;
+mod guard;
+
+pub use captcha::*;
+pub use form_token::*;
+pub use guard::*;
@@ -0,0 +1,166 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{create_user, Role};
+use crate::configuration::Settings;
+use crate::domain::SubscriberEmail;
+use crate::routes::{
+    import_subscribers, ConfirmationReport, ErasureKey, ImportMode, ImportReport,
+    PendingConfirmations,
+};
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use secrecy::SecretBox;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(clap::Parser)]
+#[command(name = "zero2prod", about = "A newsletter delivery service")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    #[command(flatten)]
+    Task(Task),
+    /// Inspect the configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+/// The commands doing the work of the service, with logging set up.
+#[derive(clap::Subcommand)]
+pub enum Task {
+    /// Start the HTTP server. This is the default when no subcommand is given.
+    Serve,
+    /// Import subscribers from a CSV file with `email` and `name` columns.
+    ImportSubscribers {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = ImportMode::SendConfirmation)]
+        mode: ImportMode,
+    },
+    /// Create an admin user, reading their password from the first line of stdin.
+    CreateUser {
+        username: String,
+        #[arg(long, default_value = "admin", value_parser = parse_role)]
+        role: Role,
+        /// Address used to send password reset links.
+        #[arg(long)]
+        email: Option<String>,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum ConfigCommand {
+    /// Read and validate the configuration, listing every problem found,
+    /// without starting the server.
+    Check,
+}
+
+fn parse_role(role: &str) -> Result<Role, String> {
+    Role::try_from(role.to_string())
+}
+
+/// Imports the subscribers of `file`, returning the confirmation emails still
+/// to send so that the report can be printed first.
+pub async fn import_subscribers_from_file(
+    configuration: &Settings,
+    file: &Path,
+    mode: ImportMode,
+) -> Result<(ImportReport, PendingConfirmations), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    let canonicalizer = configuration.email_validation.canonicalizer();
+    let file = tokio::fs::File::open(file)
+        .await
+        .with_context(|| format!("Failed to open {}", file.display()))?;
+    let (report, confirmations) = import_subscribers(
+        file,
+        mode,
+        &pool,
+        &ErasureKey::new(&configuration.gdpr, canonicalizer),
+        &configuration.email_validation.policy()?,
+        &canonicalizer,
+    )
+    .await?;
+    record_audit_event(&pool, report.audit_event(cli_actor(), mode))
+        .await
+        .context("Failed to record the import in the audit log.")?;
+    Ok((report, confirmations))
+}
+
+/// Sends the confirmation emails of an import, recording how it went in the audit log.
+pub async fn send_import_confirmations(
+    configuration: &Settings,
+    confirmations: PendingConfirmations,
+) -> Result<ConfirmationReport, anyhow::Error> {
+    if confirmations.is_empty() {
+        return Ok(ConfirmationReport::default());
+    }
+    let email_client = configuration
+        .email_client
+        .client()
+        .context("Failed to build the email client.")?;
+    let report = confirmations
+        .send(&email_client, &configuration.application.base_url)
+        .await;
+    let pool = get_connection_pool(&configuration.database);
+    record_audit_event(&pool, report.audit_event(cli_actor()))
+        .await
+        .context("Failed to record the confirmations of the import in the audit log.")?;
+    Ok(report)
+}
+
+pub async fn create_user_from_stdin(
+    configuration: &Settings,
+    username: &str,
+    role: Role,
+    email: Option<String>,
+) -> Result<Uuid, anyhow::Error> {
+    let email = email.map(SubscriberEmail::parse).transpose()?;
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .context("Failed to read the password from stdin")?;
+    let password = SecretBox::new(Box::new(
+        password.trim_end_matches(['\r', '\n']).to_string(),
+    ));
+    configuration
+        .password_policy
+        .policy()
+        .context("Failed to read the breached passwords.")?
+        .validate(username, &password)
+        .map_err(anyhow::Error::msg)?;
+
+    let pool = get_connection_pool(&configuration.database);
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let user_id = create_user(
+        &mut *transaction,
+        username,
+        password,
+        role,
+        email.as_ref().map(|e| e.as_ref()),
+    )
+    .await?;
+    let event = AuditEvent::new(cli_actor(), "user.create")
+        .target(user_id.to_string())
+        .after(serde_json::json!({ "username": username, "role": role.as_str() }));
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the new user in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a user.")?;
+    Ok(user_id)
+}
+
+/// Audit log actor for commands run from a shell on the server.
+fn cli_actor() -> String {
+    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".into());
+    format!("cli:{user}")
+}
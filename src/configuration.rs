@@ -1,13 +1,33 @@
 use secrecy::{ExposeSecret, SecretBox};
 use sqlx::postgres::PgConnectOptions;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
 
-use crate::domain::SubscriberEmail;
+use crate::authentication::PasswordPolicy;
+use crate::bot_protection::{CaptchaVerifier, HttpCaptchaVerifier, NoCaptcha};
+use crate::domain::{EmailCanonicalizer, SubscriberEmail, SubscriberEmailError};
+use crate::email_client::EmailClient;
+use crate::email_validation::{DnsMxResolver, DomainList, EmailDomainPolicy, MxResolver};
+use crate::redaction::RedactionMode;
 
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub database: DatabasesSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub login_protection: LoginProtectionSettings,
+    #[serde(default)]
+    pub password_policy: PasswordPolicySettings,
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub email_validation: EmailValidationSettings,
+    pub gdpr: GdprSettings,
+    pub health: HealthSettings,
+    pub metrics: MetricsSettings,
+    pub tracing: TracingSettings,
+    pub logging: LoggingSettings,
 }
 
 #[derive(serde::Deserialize)]
@@ -27,13 +47,16 @@ pub struct DatabasesSettings {
 }
 
 impl DatabasesSettings {
-    pub fn pg_connection(&self) -> PgConnectOptions {
+    pub fn without_db(&self) -> PgConnectOptions {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
-            .database(&self.database_name)
+    }
+
+    pub fn pg_connection(&self) -> PgConnectOptions {
+        self.without_db().database(&self.database_name)
     }
 }
 
@@ -46,7 +69,16 @@ pub struct EmailClientSettings {
 }
 
 impl EmailClientSettings {
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+    pub fn client(&self) -> Result<EmailClient, SubscriberEmailError> {
+        Ok(EmailClient::new(
+            &self.base_url,
+            self.sender()?,
+            &self.authorization_token,
+            self.timeout(),
+        ))
+    }
+
+    pub fn sender(&self) -> Result<SubscriberEmail, SubscriberEmailError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
@@ -55,6 +87,291 @@ impl EmailClientSettings {
     }
 }
 
+/// Thresholds against password guessing on the login and 'Basic' auth paths.
+#[derive(serde::Deserialize, Clone)]
+pub struct LoginProtectionSettings {
+    /// Failures older than this are forgotten.
+    pub window_seconds: u64,
+    pub max_failures_per_username: usize,
+    pub max_failures_per_ip: usize,
+    pub lockout_seconds: u64,
+    /// Added to the response time for every recent failure, up to `max_delay_milliseconds`.
+    pub delay_step_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+}
+
+impl LoginProtectionSettings {
+    pub fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.window_seconds)
+    }
+
+    pub fn lockout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.lockout_seconds)
+    }
+
+    pub fn delay_for(&self, failures: usize) -> std::time::Duration {
+        let delay = self.delay_step_milliseconds.saturating_mul(failures as u64);
+        std::time::Duration::from_millis(delay.min(self.max_delay_milliseconds))
+    }
+}
+
+/// Limits on the public endpoints, which anyone can use to make us send emails.
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub store: RateLimitStoreKind,
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted to name the client.
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+    pub per_ip: TokenBucketSettings,
+    pub per_email: TokenBucketSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    /// Per instance, lost on restart.
+    Memory,
+    /// Shared by every instance using the same database.
+    Postgres,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct TokenBucketSettings {
+    /// How many requests can be made in a burst.
+    pub capacity: u32,
+    pub refill_per_minute: u32,
+}
+
+impl TokenBucketSettings {
+    pub fn refill_per_second(&self) -> f64 {
+        f64::from(self.refill_per_minute) / 60.0
+    }
+}
+
+/// Checks telling people from bots on the subscription form.
+#[derive(serde::Deserialize)]
+pub struct BotProtectionSettings {
+    /// Signs the timestamp handed out when the form is rendered.
+    pub form_token_secret: SecretBox<String>,
+    /// Whether submissions without a form token are refused. Tokens sent are
+    /// checked either way, but without this a bot skips the timing check by
+    /// leaving the token out: it is on in production.
+    pub require_form_token: bool,
+    /// Submissions quicker than this after rendering the form are from bots.
+    pub min_fill_seconds: u64,
+    pub max_form_age_seconds: u64,
+    pub captcha: CaptchaSettings,
+}
+
+impl BotProtectionSettings {
+    pub fn min_fill_time(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.min_fill_seconds)
+    }
+
+    pub fn max_form_age(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.max_form_age_seconds)
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum CaptchaSettings {
+    None,
+    /// A `siteverify` endpoint, as offered by reCAPTCHA, hCaptcha or Turnstile.
+    Http {
+        verify_url: String,
+        secret: SecretBox<String>,
+        timeout_milliseconds: u64,
+    },
+}
+
+impl CaptchaSettings {
+    pub fn verifier(&self) -> Box<dyn CaptchaVerifier> {
+        match self {
+            CaptchaSettings::None => Box::new(NoCaptcha),
+            CaptchaSettings::Http {
+                verify_url,
+                secret,
+                timeout_milliseconds,
+            } => {
+                let secret = SecretBox::new(Box::new(secret.expose_secret().clone()));
+                Box::new(HttpCaptchaVerifier::new(
+                    verify_url,
+                    secret,
+                    std::time::Duration::from_millis(*timeout_milliseconds),
+                ))
+            }
+        }
+    }
+}
+
+/// Dependency checks of the readiness probe.
+#[derive(serde::Deserialize, Clone)]
+pub struct HealthSettings {
+    /// How long each dependency gets to answer before it is reported down.
+    pub timeout_milliseconds: u64,
+    /// The email provider is not required to serve traffic, so it is only
+    /// reported on, and only when enabled.
+    pub probe_email_provider: bool,
+}
+
+impl HealthSettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct MetricsSettings {
+    /// Serves `/metrics` on this port instead of alongside the API.
+    pub admin_port: Option<u16>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoggingSettings {
+    /// How subscriber emails and names are written to logs and spans.
+    pub pii: RedactionMode,
+    /// Keys the hashes of the `hashed` mode, so that they cannot be reversed
+    /// by hashing candidate addresses.
+    pub pii_hash_key: SecretBox<String>,
+    /// The format of the logs written to stdout.
+    pub format: LogFormat,
+    /// Directives such as `info,sqlx=warn`. `RUST_LOG` takes precedence, and
+    /// admins can change them at runtime.
+    pub filter: String,
+    /// Also writes the logs to files, rotated as configured.
+    pub file: Option<LogFileSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Bunyan JSON, one object per line.
+    Json,
+    /// Multi-line and human readable, for local debugging.
+    Pretty,
+    /// One human-readable line per event.
+    Compact,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LogFileSettings {
+    pub directory: String,
+    /// Files are named `<prefix>.<date>.log`.
+    pub file_name_prefix: String,
+    pub rotation: LogRotation,
+    pub format: LogFormat,
+}
+
+impl LogFileSettings {
+    pub fn appender(&self) -> Result<RollingFileAppender, InitError> {
+        let rotation = match self.rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        };
+        RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix(&self.file_name_prefix)
+            .filename_suffix("log")
+            .build(&self.directory)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    /// A single file, rotated by other means.
+    Never,
+}
+
+/// Where spans are exported to, on top of the JSON logs.
+#[derive(serde::Deserialize)]
+pub struct TracingSettings {
+    /// Base URL of an OpenTelemetry collector accepting OTLP over HTTP, e.g.
+    /// `http://localhost:4318`. Spans are not exported when unset.
+    pub otlp_endpoint: Option<String>,
+    pub export_timeout_milliseconds: u64,
+}
+
+impl TracingSettings {
+    pub fn export_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.export_timeout_milliseconds)
+    }
+}
+
+/// Checks on the domain of subscriber emails, on top of their syntax.
+#[derive(serde::Deserialize)]
+pub struct EmailValidationSettings {
+    /// Always accepted, even if disposable or without MX records.
+    pub allowed_domains: Vec<String>,
+    pub denied_domains: Vec<String>,
+    pub block_disposable_domains: bool,
+    /// Extra disposable domains, one per line, on top of the bundled list.
+    #[serde(default)]
+    pub disposable_domains_file: Option<String>,
+    /// Whether `u.ser+tag@gmail.com` and the like are the same subscriber as
+    /// `user@gmail.com`. See [`EmailCanonicalizer`] before changing it.
+    pub canonicalize_provider_aliases: bool,
+    pub check_mx: bool,
+    pub mx_timeout_milliseconds: u64,
+}
+
+impl EmailValidationSettings {
+    pub fn canonicalizer(&self) -> EmailCanonicalizer {
+        EmailCanonicalizer::new(self.canonicalize_provider_aliases)
+    }
+
+    pub fn policy(&self) -> Result<EmailDomainPolicy, std::io::Error> {
+        let mut disposable = DomainList::default();
+        if self.block_disposable_domains {
+            disposable.extend(DomainList::bundled_disposable());
+            if let Some(path) = &self.disposable_domains_file {
+                disposable.extend(DomainList::parse(&std::fs::read_to_string(path)?));
+            }
+        }
+        let mx_resolver: Option<Box<dyn MxResolver>> = self.check_mx.then(|| {
+            Box::new(DnsMxResolver::new(std::time::Duration::from_millis(
+                self.mx_timeout_milliseconds,
+            ))) as Box<dyn MxResolver>
+        });
+        Ok(EmailDomainPolicy::new(
+            self.allowed_domains.iter().collect(),
+            self.denied_domains.iter().collect(),
+            disposable,
+            mx_resolver,
+        ))
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+pub struct PasswordPolicySettings {
+    /// Breached passwords, one per line, on top of the bundled list: a full
+    /// corpus, such as one of the lists derived from Have I Been Pwned.
+    #[serde(default)]
+    pub breached_passwords_file: Option<String>,
+}
+
+impl PasswordPolicySettings {
+    pub fn policy(&self) -> Result<PasswordPolicy, std::io::Error> {
+        let mut policy = PasswordPolicy::default();
+        if let Some(path) = &self.breached_passwords_file {
+            policy.extend_breached_passwords(&std::fs::read_to_string(path)?);
+        }
+        Ok(policy)
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct GdprSettings {
+    /// Keys the hashes of erased addresses, so that they cannot be recovered
+    /// by hashing candidate addresses.
+    pub erasure_key: SecretBox<String>,
+}
+
 pub enum Environment {
     Dev,
     Prod,
@@ -82,22 +399,503 @@ impl TryFrom<String> for Environment {
     }
 }
 
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigurationError {
+    #[error("Failed to read the configuration: {0}")]
+    Unreadable(#[from] config::ConfigError),
+    #[error(
+        "The configuration is invalid:{}",
+        .0.iter().map(|setting| format!("\n  - {setting}")).collect::<String>()
+    )]
+    Invalid(Vec<InvalidSetting>),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("`{key}` {problem}")]
+pub struct InvalidSetting {
+    pub key: String,
+    pub problem: String,
+}
+
+impl Settings {
+    /// Every problem with the settings rather than the first one, so that they
+    /// can all be fixed at once, before they fail a request.
+    pub fn validate(&self) -> Vec<InvalidSetting> {
+        let mut problems = Vec::new();
+        let mut check = |key: &str, problem: Option<String>| {
+            if let Some(problem) = problem {
+                problems.push(InvalidSetting {
+                    key: key.into(),
+                    problem,
+                });
+            }
+        };
+        check(
+            "email_client.sender_email",
+            self.email_client
+                .sender()
+                .err()
+                .map(|e| format!("is not a valid email address: {e}")),
+        );
+        check(
+            "application.base_url",
+            url_problem(&self.application.base_url),
+        );
+        check(
+            "database.host",
+            self.database
+                .host
+                .trim()
+                .is_empty()
+                .then(|| "is missing".to_string()),
+        );
+        check(
+            "email_client.base_url",
+            url_problem(&self.email_client.base_url),
+        );
+        if let Some(endpoint) = &self.tracing.otlp_endpoint {
+            check("tracing.otlp_endpoint", url_problem(endpoint));
+        }
+        if let CaptchaSettings::Http {
+            verify_url,
+            secret,
+            timeout_milliseconds,
+        } = &self.bot_protection.captcha
+        {
+            check("bot_protection.captcha.verify_url", url_problem(verify_url));
+            check(
+                "bot_protection.captcha.secret",
+                secret
+                    .expose_secret()
+                    .is_empty()
+                    .then(|| "is missing".to_string()),
+            );
+            check(
+                "bot_protection.captcha.timeout_milliseconds",
+                zero_problem(*timeout_milliseconds),
+            );
+        }
+        let amounts = [
+            (
+                "email_client.timeout_milliseconds",
+                self.email_client.timeout_milliseconds,
+            ),
+            (
+                "health.timeout_milliseconds",
+                self.health.timeout_milliseconds,
+            ),
+            (
+                "email_validation.mx_timeout_milliseconds",
+                self.email_validation.mx_timeout_milliseconds,
+            ),
+            (
+                "tracing.export_timeout_milliseconds",
+                self.tracing.export_timeout_milliseconds,
+            ),
+            (
+                "rate_limit.per_ip.capacity",
+                self.rate_limit.per_ip.capacity.into(),
+            ),
+            (
+                "rate_limit.per_ip.refill_per_minute",
+                self.rate_limit.per_ip.refill_per_minute.into(),
+            ),
+            (
+                "rate_limit.per_email.capacity",
+                self.rate_limit.per_email.capacity.into(),
+            ),
+            (
+                "rate_limit.per_email.refill_per_minute",
+                self.rate_limit.per_email.refill_per_minute.into(),
+            ),
+        ];
+        for (key, amount) in amounts {
+            check(key, zero_problem(amount));
+        }
+        // Port 0 lets the OS pick a free port, which cannot conflict.
+        if let Some(admin_port) = self.metrics.admin_port {
+            if admin_port != 0 && admin_port == self.application.port {
+                check(
+                    "metrics.admin_port",
+                    Some(format!(
+                        "is also `application.port` ({admin_port}): use another port or unset it to serve the metrics alongside the API"
+                    )),
+                );
+            }
+        }
+        check(
+            "logging.filter",
+            EnvFilter::try_new(&self.logging.filter)
+                .err()
+                .map(|e| format!("is not a valid filter: {e}")),
+        );
+        if let Some(path) = &self.email_validation.disposable_domains_file {
+            check(
+                "email_validation.disposable_domains_file",
+                std::fs::metadata(path)
+                    .err()
+                    .map(|e| format!("cannot be read from {path}: {e}")),
+            );
+        }
+        if let Some(path) = &self.password_policy.breached_passwords_file {
+            check(
+                "password_policy.breached_passwords_file",
+                std::fs::metadata(path)
+                    .err()
+                    .map(|e| format!("cannot be read from {path}: {e}")),
+            );
+        }
+        problems
+    }
+}
+
+fn url_problem(url: &str) -> Option<String> {
+    match reqwest::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => None,
+        Ok(url) => Some(format!(
+            "must be an http or https URL, not {}",
+            url.scheme()
+        )),
+        Err(e) => Some(format!("is not a valid URL ({url}): {e}")),
+    }
+}
+
+fn zero_problem(amount: u64) -> Option<String> {
+    (amount == 0).then(|| "must be greater than 0".to_string())
+}
+
+/// Required in production: their values in `base.yaml` are only fit for development.
+const SECRETS: [&str; 5] = [
+    "database.password",
+    "email_client.authorization_token",
+    "bot_protection.form_token_secret",
+    "gdpr.erasure_key",
+    "logging.pii_hash_key",
+];
+
+/// Secrets which are missing or still set to their development values.
+fn development_secrets(
+    configuration: &config::Config,
+    base: &config::Config,
+) -> Vec<InvalidSetting> {
+    SECRETS
+        .iter()
+        .filter_map(|key| {
+            let value = configuration.get_string(key).unwrap_or_default();
+            let problem = if value.is_empty() {
+                "is missing"
+            } else if base.get_string(key).is_ok_and(|default| default == value) {
+                "still has its development value from base.yaml"
+            } else {
+                return None;
+            };
+            let variable = format!(
+                "{ENV_PREFIX}_{}",
+                key.to_uppercase().replace('.', ENV_SEPARATOR)
+            );
+            Some(InvalidSetting {
+                key: key.to_string(),
+                problem: format!("{problem}: set {variable} or {variable}{FILE_SUFFIX}"),
+            })
+        })
+        .collect()
+}
+
+/// Prefix of the environment variables overriding the configuration files.
+/// Nested keys are separated by `__`: `APP_DATABASE__HOST` sets `database.host`.
+const ENV_PREFIX: &str = "APP";
+const ENV_SEPARATOR: &str = "__";
+/// Suffix of the variables naming a file to read a setting from, so that
+/// secrets can be mounted rather than exposed in the environment:
+/// `APP_DATABASE__PASSWORD_FILE=/run/secrets/db_password`.
+const FILE_SUFFIX: &str = "_FILE";
+/// Settings named like indirections, which are set as they are.
+const FILE_SETTINGS: [&str; 3] = [
+    "logging.file",
+    "email_validation.disposable_domains_file",
+    "password_policy.breached_passwords_file",
+];
+/// Settings holding lists, given as comma-separated values in the environment.
+const LIST_KEYS: [&str; 3] = [
+    "rate_limit.trusted_proxies",
+    "email_validation.allowed_domains",
+    "email_validation.denied_domains",
+];
+
+/// Reads `base.yaml`, then `{APP_ENVIRONMENT}.yaml`, then the `APP_` environment
+/// variables, each overriding the previous ones, and validates the result.
+pub fn get_configuration() -> Result<Settings, ConfigurationError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
-    let configuration_directory = base_path.join("configuration");
+    load_configuration(&base_path.join("configuration"), std::env::vars().collect())
+}
 
-    let environment: Environment = std::env::var("APP_ENVIRONMENT")
+fn load_configuration(
+    configuration_directory: &Path,
+    variables: HashMap<String, String>,
+) -> Result<Settings, ConfigurationError> {
+    let environment: Environment = variables
+        .get("APP_ENVIRONMENT")
+        .cloned()
         .unwrap_or("dev".to_string())
         .try_into()
-        .expect("Failed to parse APP_ENVIRONMENT.");
+        .map_err(|e| config::ConfigError::Message(format!("Invalid APP_ENVIRONMENT: {e}")))?;
+    let in_production = matches!(environment, Environment::Prod);
     let environment_filename = format!("{}.yaml", environment.as_str());
-    let settings = config::Config::builder()
+    let secret_files = secret_files(&variables)?;
+    // Split here rather than by `config`, which only splits lists when it also
+    // parses every other value as a number, mangling secrets such as `0123`.
+    let (lists, variables): (HashMap<_, _>, HashMap<_, _>) = variables
+        .into_iter()
+        .partition(|(name, _)| list_key(name).is_some());
+    let mut builder = config::Config::builder()
         .add_source(config::File::from(
             configuration_directory.join("base.yaml"),
         ))
         .add_source(config::File::from(
             configuration_directory.join(environment_filename),
         ))
-        .build()?;
-    settings.try_deserialize::<Settings>()
+        .add_source(
+            config::Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator("_")
+                .separator(ENV_SEPARATOR)
+                .source(Some(variables.into_iter().collect())),
+        );
+    for (name, value) in lists {
+        let values: Vec<&str> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect();
+        builder = builder.set_override(list_key(&name).unwrap(), values)?;
+    }
+    for (key, value) in secret_files {
+        builder = builder.set_override(key, value)?;
+    }
+    let configuration = builder.build()?;
+    let settings = configuration.clone().try_deserialize::<Settings>()?;
+    let mut problems = settings.validate();
+    if in_production {
+        let base = config::Config::builder()
+            .add_source(config::File::from(
+                configuration_directory.join("base.yaml"),
+            ))
+            .build()?;
+        problems.extend(development_secrets(&configuration, &base));
+    }
+    if problems.is_empty() {
+        Ok(settings)
+    } else {
+        Err(ConfigurationError::Invalid(problems))
+    }
+}
+
+/// Reads the files named by `APP_*_FILE` variables, keyed by the setting they
+/// are for. A trailing newline, as left by most editors, is not part of the value.
+fn secret_files(
+    variables: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>, config::ConfigError> {
+    let mut secrets = Vec::new();
+    for (name, path) in variables {
+        let Some(setting) = name
+            .strip_prefix(&format!("{ENV_PREFIX}_"))
+            .filter(|setting| !FILE_SETTINGS.contains(&setting_key(setting).as_str()))
+            .and_then(|setting| setting.strip_suffix(FILE_SUFFIX))
+        else {
+            continue;
+        };
+        if variables.contains_key(&format!("{ENV_PREFIX}_{setting}")) {
+            return Err(config::ConfigError::Message(format!(
+                "Both {ENV_PREFIX}_{setting} and {name} are set. Only set one of them."
+            )));
+        }
+        let value = std::fs::read_to_string(path).map_err(|e| {
+            config::ConfigError::Message(format!("Failed to read {name} from {path}: {e}."))
+        })?;
+        secrets.push((
+            setting_key(setting),
+            value.trim_end_matches(['\r', '\n']).to_string(),
+        ));
+    }
+    Ok(secrets)
+}
+
+/// The setting a variable sets, if it is one of [`LIST_KEYS`].
+fn list_key(variable: &str) -> Option<String> {
+    variable
+        .strip_prefix(&format!("{ENV_PREFIX}_"))
+        .map(setting_key)
+        .filter(|key| LIST_KEYS.contains(&key.as_str()))
+}
+
+/// `DATABASE__PASSWORD` is `database.password`.
+fn setting_key(variable: &str) -> String {
+    variable.to_lowercase().replace(ENV_SEPARATOR, ".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_configuration, CaptchaSettings, ConfigurationError};
+    use secrecy::{ExposeSecret, SecretBox};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn load(variables: &[(&str, &str)]) -> Result<super::Settings, ConfigurationError> {
+        let variables: HashMap<_, _> = variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        load_configuration(Path::new("configuration"), variables)
+    }
+
+    #[test]
+    fn environment_variables_override_nested_settings() {
+        let settings = load(&[
+            ("APP_DATABASE__HOST", "db.internal"),
+            ("APP_DATABASE__PORT", "6543"),
+            ("APP_HEALTH__PROBE_EMAIL_PROVIDER", "true"),
+            ("APP_METRICS__ADMIN_PORT", "9000"),
+            ("APP_RATE_LIMIT__TRUSTED_PROXIES", "10.0.0.1,10.0.0.2"),
+        ])
+        .unwrap();
+
+        assert_eq!(settings.database.host, "db.internal");
+        assert_eq!(settings.database.port, 6543);
+        assert!(settings.health.probe_email_provider);
+        assert_eq!(settings.metrics.admin_port, Some(9000));
+        assert_eq!(settings.rate_limit.trusted_proxies.len(), 2);
+    }
+
+    #[test]
+    fn numeric_strings_are_kept_as_strings_where_strings_are_expected() {
+        let settings = load(&[("APP_DATABASE__PASSWORD", "0123456")]).unwrap();
+
+        assert_eq!(settings.database.password.expose_secret(), "0123456");
+    }
+
+    #[test]
+    fn secrets_can_be_read_from_files() {
+        let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::write(&path, "s3cr3t\n").unwrap();
+
+        let settings = load(&[
+            ("APP_DATABASE__PASSWORD_FILE", path.to_str().unwrap()),
+            (
+                "APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN_FILE",
+                path.to_str().unwrap(),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(settings.database.password.expose_secret(), "s3cr3t");
+        assert_eq!(
+            settings.email_client.authorization_token.expose_secret(),
+            "s3cr3t"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_missing_secret_file_is_an_error() {
+        let error = load(&[("APP_DATABASE__PASSWORD_FILE", "/does/not/exist")])
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("APP_DATABASE__PASSWORD_FILE"));
+    }
+
+    #[test]
+    fn a_secret_cannot_be_set_both_directly_and_from_a_file() {
+        let result = load(&[
+            ("APP_DATABASE__PASSWORD", "password"),
+            ("APP_DATABASE__PASSWORD_FILE", "/run/secrets/db_password"),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn settings_named_like_files_are_not_indirections() {
+        let settings = load(&[(
+            "APP_EMAIL_VALIDATION__DISPOSABLE_DOMAINS_FILE",
+            "configuration/base.yaml",
+        )])
+        .unwrap();
+
+        assert_eq!(
+            settings.email_validation.disposable_domains_file.as_deref(),
+            Some("configuration/base.yaml")
+        );
+    }
+
+    fn invalid_keys(result: Result<super::Settings, ConfigurationError>) -> Vec<String> {
+        match result {
+            Err(ConfigurationError::Invalid(problems)) => {
+                problems.into_iter().map(|problem| problem.key).collect()
+            }
+            Err(e) => panic!("Expected invalid settings, got: {e}"),
+            Ok(_) => panic!("Expected invalid settings."),
+        }
+    }
+
+    #[test]
+    fn every_invalid_setting_is_reported_at_once() {
+        let result = load(&[
+            ("APP_EMAIL_CLIENT__SENDER_EMAIL", "not-an-email"),
+            ("APP_APPLICATION__BASE_URL", "localhost:8000"),
+            ("APP_EMAIL_CLIENT__TIMEOUT_MILLISECONDS", "0"),
+            ("APP_APPLICATION__PORT", "9000"),
+            ("APP_METRICS__ADMIN_PORT", "9000"),
+        ]);
+
+        let keys = invalid_keys(result);
+        assert_eq!(
+            keys,
+            [
+                "email_client.sender_email",
+                "application.base_url",
+                "email_client.timeout_milliseconds",
+                "metrics.admin_port",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_database_host_and_the_captcha_secret_are_required() {
+        let mut settings = load(&[]).unwrap();
+        settings.database.host = String::new();
+        settings.bot_protection.captcha = CaptchaSettings::Http {
+            verify_url: "https://captcha.example/siteverify".into(),
+            secret: SecretBox::new(Box::default()),
+            timeout_milliseconds: 1000,
+        };
+
+        let keys: Vec<_> = settings
+            .validate()
+            .into_iter()
+            .map(|problem| problem.key)
+            .collect();
+        assert_eq!(keys, ["database.host", "bot_protection.captcha.secret"]);
+    }
+
+    #[test]
+    fn production_requires_its_own_secrets() {
+        let keys = invalid_keys(load(&[("APP_ENVIRONMENT", "prod")]));
+        assert_eq!(keys, super::SECRETS);
+
+        let settings = load(&[
+            ("APP_ENVIRONMENT", "prod"),
+            ("APP_DATABASE__PASSWORD", "db-secret"),
+            ("APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN", "email-secret"),
+            ("APP_BOT_PROTECTION__FORM_TOKEN_SECRET", "form-secret"),
+            ("APP_GDPR__ERASURE_KEY", "erasure-key"),
+            ("APP_LOGGING__PII_HASH_KEY", "pii-hash-key"),
+        ])
+        .unwrap();
+        assert_eq!(settings.database.host, "172.17.0.2");
+        assert!(settings.bot_protection.require_form_token);
+    }
+
+    #[test]
+    fn an_unknown_environment_is_an_error_rather_than_a_panic() {
+        let error = load(&[("APP_ENVIRONMENT", "staging")]).err().unwrap();
+        assert!(error.to_string().contains("staging"));
+    }
 }
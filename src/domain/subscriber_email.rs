@@ -1,34 +1,173 @@
+use crate::domain::FieldValidationError;
+use crate::redaction::Redacted;
+use serde_json::{Map, Value};
 use validator::validate_email;
 
-#[derive(Debug)]
-pub struct SubscriberEmail(String);
+/// Providers ignoring dots in the local part, mapped to their main domain.
+const DOTLESS_PROVIDERS: [(&str, &str); 2] =
+    [("gmail.com", "gmail.com"), ("googlemail.com", "gmail.com")];
+/// Providers delivering `user+tag@` to `user@`.
+const SUBADDRESSING_PROVIDERS: [&str; 9] = [
+    "gmail.com",
+    "outlook.com",
+    "hotmail.com",
+    "live.com",
+    "icloud.com",
+    "me.com",
+    "fastmail.com",
+    "protonmail.com",
+    "proton.me",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SubscriberEmailError {
+    #[error("The email is empty.")]
+    EmptyEmail,
+    #[error("The email is not a valid address.")]
+    InvalidEmailSyntax,
+    #[error("Email addresses at {0} are not accepted.")]
+    DeniedDomain(String),
+    #[error("{0} is a disposable email provider. Use a permanent address.")]
+    DisposableDomain(String),
+    #[error("{0} cannot receive email.")]
+    UndeliverableDomain(String),
+}
+
+impl FieldValidationError for SubscriberEmailError {
+    fn code(&self) -> &'static str {
+        match self {
+            SubscriberEmailError::EmptyEmail => "empty_email",
+            SubscriberEmailError::InvalidEmailSyntax => "invalid_email_syntax",
+            SubscriberEmailError::DeniedDomain(_) => "denied_domain",
+            SubscriberEmailError::DisposableDomain(_) => "disposable_domain",
+            SubscriberEmailError::UndeliverableDomain(_) => "undeliverable_domain",
+        }
+    }
+
+    fn params(&self) -> Map<String, Value> {
+        let mut params = Map::new();
+        match self {
+            SubscriberEmailError::EmptyEmail | SubscriberEmailError::InvalidEmailSyntax => {}
+            SubscriberEmailError::DeniedDomain(domain)
+            | SubscriberEmailError::DisposableDomain(domain)
+            | SubscriberEmailError::UndeliverableDomain(domain) => {
+                params.insert("domain".into(), domain.clone().into());
+            }
+        }
+        params
+    }
+}
+
+/// An email address, as typed by the subscriber and in canonical form.
+///
+/// The display form is what we send emails to. The canonical form identifies
+/// the mailbox: two addresses with the same canonical form reach the same
+/// person, so it is what uniqueness is enforced on.
+///
+/// It is redacted when debugged, so that it can be recorded in spans as is.
+pub struct SubscriberEmail {
+    display: String,
+    canonical: String,
+}
+
+impl std::fmt::Debug for SubscriberEmail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SubscriberEmail")
+            .field(&Redacted(&self.display))
+            .finish()
+    }
+}
 
 impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        self.display.fmt(f)
     }
 }
 
 impl SubscriberEmail {
-    pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{s} is not a valid subscriber email."))
+    /// Parses an address that does not identify a subscriber, so its canonical
+    /// form does not apply the rules of any provider.
+    pub fn parse(s: String) -> Result<SubscriberEmail, SubscriberEmailError> {
+        EmailCanonicalizer::default().parse(s)
+    }
+
+    pub fn canonical(&self) -> &str {
+        &self.canonical
+    }
+}
+
+/// Computes the canonical form of subscriber addresses: lowercased, with
+/// internationalised domains encoded as punycode and, if enabled, the rules of
+/// the providers known to route several addresses to the same mailbox applied.
+///
+/// The canonical forms are stored in `subscriptions.email_canonical`, so the
+/// rules must not change without recomputing them, as the migration adding the
+/// column did, and merging the subscribers that end up sharing one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmailCanonicalizer {
+    provider_aliases: bool,
+}
+
+impl EmailCanonicalizer {
+    pub fn new(provider_aliases: bool) -> Self {
+        Self { provider_aliases }
+    }
+
+    pub fn parse(&self, s: String) -> Result<SubscriberEmail, SubscriberEmailError> {
+        let trimmed = s.trim();
+        if trimmed.is_empty() {
+            return Err(SubscriberEmailError::EmptyEmail);
         }
+        trimmed
+            .rsplit_once('@')
+            .filter(|_| validate_email(trimmed))
+            .and_then(|(local, domain)| {
+                Some(SubscriberEmail {
+                    canonical: self.canonicalize(local, domain)?,
+                    display: format!("{local}@{}", domain.to_lowercase()),
+                })
+            })
+            .ok_or(SubscriberEmailError::InvalidEmailSyntax)
+    }
+
+    /// The canonical form of `s`, or its trimmed lowercase form if it is not a
+    /// valid address, for lookups that must not fail on malformed input.
+    pub fn canonical_email(&self, s: &str) -> String {
+        self.parse(s.to_string())
+            .map(|email| email.canonical)
+            .unwrap_or_else(|_| s.trim().to_lowercase())
+    }
+
+    fn canonicalize(&self, local: &str, domain: &str) -> Option<String> {
+        let mut domain = idna::domain_to_ascii(domain).ok()?;
+        let mut local = local.to_lowercase();
+        if self.provider_aliases {
+            if let Some((_, main_domain)) = DOTLESS_PROVIDERS.iter().find(|(d, _)| *d == domain) {
+                local.retain(|c| c != '.');
+                domain = main_domain.to_string();
+            }
+            if SUBADDRESSING_PROVIDERS.contains(&domain.as_str()) {
+                if let Some((user, _tag)) = local.split_once('+') {
+                    local = user.to_string();
+                }
+            }
+        }
+        if local.is_empty() {
+            return None;
+        }
+        Some(format!("{local}@{domain}"))
     }
 }
 
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.display
     }
 }
 
 #[cfg(test)]
-
 mod tests {
-    use super::SubscriberEmail;
+    use super::{EmailCanonicalizer, SubscriberEmail, SubscriberEmailError};
     use claims::assert_err;
 
     use fake::faker::internet::en::SafeEmail;
@@ -42,14 +181,20 @@ mod tests {
 
     #[test]
     fn empty_string_is_rejected() {
-        let email = "".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        let email = " ".to_string();
+        assert_eq!(
+            SubscriberEmail::parse(email).unwrap_err(),
+            SubscriberEmailError::EmptyEmail
+        );
     }
 
     #[test]
     fn email_missing_at_symbol_is_rejected() {
         let email = "ursuladomain.com".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        assert_eq!(
+            SubscriberEmail::parse(email).unwrap_err(),
+            SubscriberEmailError::InvalidEmailSyntax
+        );
     }
 
     #[test]
@@ -57,4 +202,62 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn debug_output_does_not_reveal_the_address() {
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        assert!(!format!("{email:?}").contains("ursula"));
+    }
+
+    fn canonical(email: &str) -> String {
+        EmailCanonicalizer::new(true)
+            .parse(email.to_string())
+            .unwrap()
+            .canonical()
+            .to_string()
+    }
+
+    #[test]
+    fn case_and_surrounding_whitespace_are_not_significant() {
+        let email = SubscriberEmail::parse(" Ursula@Example.COM\n".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula@example.com");
+        assert_eq!(email.canonical(), "ursula@example.com");
+    }
+
+    #[test]
+    fn internationalised_domains_are_encoded_as_punycode() {
+        assert_eq!(
+            canonical("ursula@bücher.example"),
+            "ursula@xn--bcher-kva.example"
+        );
+    }
+
+    #[test]
+    fn provider_aliases_share_a_canonical_form() {
+        assert_eq!(
+            canonical("U.Le.Guin+news@googlemail.com"),
+            "uleguin@gmail.com"
+        );
+        assert_eq!(canonical("ursula+news@outlook.com"), "ursula@outlook.com");
+        // Other providers may well treat these as different mailboxes.
+        assert_eq!(
+            canonical("u.le.guin+news@example.com"),
+            "u.le.guin+news@example.com"
+        );
+    }
+
+    #[test]
+    fn provider_rules_are_only_applied_when_enabled() {
+        let email = EmailCanonicalizer::new(false)
+            .parse("U.Le.Guin+news@googlemail.com".to_string())
+            .unwrap();
+        assert_eq!(email.canonical(), "u.le.guin+news@googlemail.com");
+    }
+
+    #[test]
+    fn a_local_part_made_only_of_ignored_characters_is_rejected() {
+        let canonicalizer = EmailCanonicalizer::new(true);
+        assert_err!(canonicalizer.parse("...@gmail.com".to_string()));
+        assert_err!(canonicalizer.parse("+news@gmail.com".to_string()));
+    }
 }
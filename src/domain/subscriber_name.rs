@@ -1,20 +1,74 @@
+use crate::domain::FieldValidationError;
+use crate::redaction::Redacted;
+use serde_json::{Map, Value};
 use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Debug)]
+/// In graphemes.
+pub const MAX_NAME_LENGTH: usize = 256;
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SubscriberNameError {
+    #[error("The name is empty.")]
+    EmptyName,
+    #[error("The name is {actual} characters long, longer than the maximum of {max}.")]
+    TooLong { max: usize, actual: usize },
+    #[error("The name contains the forbidden character {0:?}.")]
+    ForbiddenCharacter(char),
+}
+
+impl FieldValidationError for SubscriberNameError {
+    fn code(&self) -> &'static str {
+        match self {
+            SubscriberNameError::EmptyName => "empty_name",
+            SubscriberNameError::TooLong { .. } => "too_long",
+            SubscriberNameError::ForbiddenCharacter(_) => "forbidden_character",
+        }
+    }
+
+    fn params(&self) -> Map<String, Value> {
+        let mut params = Map::new();
+        match self {
+            SubscriberNameError::EmptyName => {}
+            SubscriberNameError::TooLong { max, actual } => {
+                params.insert("max".into(), (*max).into());
+                params.insert("actual".into(), (*actual).into());
+            }
+            SubscriberNameError::ForbiddenCharacter(c) => {
+                params.insert("character".into(), c.to_string().into());
+            }
+        }
+        params
+    }
+}
+
+/// Redacted when debugged, like [`SubscriberEmail`](crate::domain::SubscriberEmail).
 pub struct SubscriberName(String);
 
-impl SubscriberName {
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
-        let is_too_long = s.graphemes(true).count() > 256;
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
+impl std::fmt::Debug for SubscriberName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SubscriberName")
+            .field(&Redacted(&self.0))
+            .finish()
+    }
+}
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{s} is not a valid subscriber name."))
-        } else {
-            Ok(Self(s))
+impl SubscriberName {
+    pub fn parse(s: String) -> Result<SubscriberName, SubscriberNameError> {
+        if s.trim().is_empty() {
+            return Err(SubscriberNameError::EmptyName);
         }
+        let length = s.graphemes(true).count();
+        if length > MAX_NAME_LENGTH {
+            return Err(SubscriberNameError::TooLong {
+                max: MAX_NAME_LENGTH,
+                actual: length,
+            });
+        }
+        if let Some(c) = s.chars().find(|c| FORBIDDEN_CHARACTERS.contains(c)) {
+            return Err(SubscriberNameError::ForbiddenCharacter(c));
+        }
+        Ok(Self(s))
     }
 }
 
@@ -26,7 +80,7 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
+    use crate::domain::{SubscriberName, SubscriberNameError};
     use claims::{assert_err, assert_ok};
 
     #[test]
@@ -37,7 +91,13 @@ mod tests {
     #[test]
     fn a_name_longer_than_256_graphemes_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_eq!(
+            SubscriberName::parse(name).unwrap_err(),
+            SubscriberNameError::TooLong {
+                max: 256,
+                actual: 257
+            }
+        );
     }
 
     #[test]
@@ -49,13 +109,19 @@ mod tests {
     #[test]
     fn empty_string_is_rejected() {
         let name = "".to_string();
-        assert_err!(SubscriberName::parse(name));
+        assert_eq!(
+            SubscriberName::parse(name).unwrap_err(),
+            SubscriberNameError::EmptyName
+        );
     }
     #[test]
     fn names_containing_an_invalid_character_are_rejected() {
-        for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
-            let name = name.to_string();
-            assert_err!(SubscriberName::parse(name));
+        for c in ['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
+            let name = format!("Ursula {c}");
+            assert_eq!(
+                SubscriberName::parse(name).unwrap_err(),
+                SubscriberNameError::ForbiddenCharacter(c)
+            );
         }
     }
     #[test]
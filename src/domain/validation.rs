@@ -0,0 +1,57 @@
+use serde_json::{Map, Value};
+
+/// An invalid field, as reported to clients so that they can point at it.
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    /// Stable identifier of the failure, for clients to branch on.
+    pub code: &'static str,
+    pub message: String,
+    /// Details of the failure, such as the maximum length of a field.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+}
+
+/// An error raised while parsing a single field.
+pub trait FieldValidationError: std::error::Error {
+    fn code(&self) -> &'static str;
+
+    fn params(&self) -> Map<String, Value> {
+        Map::new()
+    }
+
+    fn for_field(&self, field: &'static str) -> FieldError {
+        FieldError {
+            field,
+            code: self.code(),
+            message: self.to_string(),
+            params: self.params(),
+        }
+    }
+}
+
+/// Every invalid field of a request.
+#[derive(Debug, PartialEq, serde::Serialize, thiserror::Error)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<FieldError> for ValidationErrors {
+    fn from(error: FieldError) -> Self {
+        Self {
+            errors: vec![error],
+        }
+    }
+}
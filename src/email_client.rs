@@ -1,12 +1,22 @@
 use crate::domain::SubscriberEmail;
+use crate::metrics::EmailMetrics;
+use crate::request_id::{RequestId, REQUEST_ID_HEADER};
+use opentelemetry_http::HeaderInjector;
+use reqwest::header::HeaderMap;
 use reqwest::Client;
 use secrecy::{ExposeSecret, SecretBox};
+use std::time::Instant;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The transport label of the email metrics: the provider's HTTP API.
+const TRANSPORT: &str = "http_api";
 
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: String,
+    metrics: EmailMetrics,
 }
 
 impl EmailClient {
@@ -22,6 +32,7 @@ impl EmailClient {
             base_url: base_url.to_string(),
             sender,
             authorization_token: authorization_token.expose_secret().into(),
+            metrics: EmailMetrics::new(),
         }
     }
 
@@ -43,13 +54,42 @@ impl EmailClient {
             subject,
             content: &content,
         };
-        self.http_client
+        // Lets the provider's traces and logs be correlated with the send.
+        let mut trace_context = HeaderMap::new();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(
+                &tracing::Span::current().context(),
+                &mut HeaderInjector(&mut trace_context),
+            )
+        });
+        if let Some(request_id) = RequestId::current() {
+            let value = request_id.to_string().parse().unwrap();
+            trace_context.insert(REQUEST_ID_HEADER, value);
+        }
+        let started_at = Instant::now();
+        let result = self
+            .http_client
             .post(&url)
             .header("Authorization", &self.authorization_token)
+            .headers(trace_context)
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await
+            .and_then(|response| response.error_for_status());
+        self.metrics
+            .observe(TRANSPORT, result.is_ok(), started_at.elapsed());
+        result?;
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> &EmailMetrics {
+        &self.metrics
+    }
+
+    /// Checks that the provider can be reached. Any HTTP answer will do,
+    /// even an error status: only connection failures and timeouts count.
+    pub async fn probe(&self) -> Result<(), reqwest::Error> {
+        self.http_client.head(&self.base_url).send().await?;
         Ok(())
     }
 }
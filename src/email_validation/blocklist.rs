@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+
+/// Disposable email providers, one domain per line. Keep it up to date with a
+/// maintained list, or point `disposable_domains_file` at a fresher one.
+const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// A set of domains, which also covers their subdomains.
+#[derive(Default, Debug)]
+pub struct DomainList(HashSet<String>);
+
+impl DomainList {
+    /// One domain per line; blank lines and `#` comments are skipped.
+    pub fn parse(lines: &str) -> Self {
+        lines
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn bundled_disposable() -> Self {
+        Self::parse(BUNDLED_DISPOSABLE_DOMAINS)
+    }
+
+    pub fn extend(&mut self, other: DomainList) {
+        self.0.extend(other.0);
+    }
+
+    /// Whether `domain`, or any domain it is a subdomain of, is in the list.
+    pub fn contains(&self, domain: &str) -> bool {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let mut candidate = domain.as_str();
+        loop {
+            if self.0.contains(candidate) {
+                return true;
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) => candidate = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for DomainList {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|d| d.as_ref().trim().trim_end_matches('.').to_lowercase())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DomainList;
+
+    #[test]
+    fn subdomains_of_a_listed_domain_are_matched() {
+        let list = DomainList::parse("# Disposable\nmailinator.com\n\n");
+        assert!(list.contains("mailinator.com"));
+        assert!(list.contains("Eu.Mailinator.com."));
+        assert!(!list.contains("notmailinator.com"));
+        assert!(!list.contains("com"));
+    }
+
+    #[test]
+    fn the_bundled_list_is_not_empty() {
+        assert!(DomainList::bundled_disposable().contains("yopmail.com"));
+    }
+}
@@ -0,0 +1,7 @@
+mod blocklist;
+mod mx;
+mod policy;
+
+pub use blocklist::*;
+pub use mx::*;
+pub use policy::*;
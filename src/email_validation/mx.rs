@@ -0,0 +1,53 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use std::time::Duration;
+
+/// Tells whether a domain can receive email.
+pub trait MxResolver: Send + Sync {
+    fn accepts_mail<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>>;
+}
+
+/// Looks the domain up in DNS.
+pub struct DnsMxResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsMxResolver {
+    /// Uses the system's DNS configuration, or public resolvers when it cannot be read.
+    pub fn new(timeout: Duration) -> Self {
+        let (config, mut options) =
+            hickory_resolver::system_conf::read_system_conf().unwrap_or_default();
+        options.timeout = timeout;
+        Self {
+            resolver: TokioAsyncResolver::tokio(config, options),
+        }
+    }
+
+    async fn lookup(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        // The trailing dot stops the resolver from trying the local search domains.
+        let fqdn = format!("{}.", domain.trim_end_matches('.'));
+        match self.resolver.mx_lookup(fqdn.as_str()).await {
+            // A single MX record pointing at `.` is a "null MX": no mail, on purpose (RFC 7505).
+            Ok(mx) => Ok(mx.iter().any(|record| !record.exchange().is_root())),
+            // Without MX records, mail goes to the domain's own address (RFC 5321).
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                match self.resolver.lookup_ip(fqdn.as_str()).await {
+                    Ok(ips) => Ok(ips.iter().next().is_some()),
+                    Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                        Ok(false)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl MxResolver for DnsMxResolver {
+    fn accepts_mail<'a>(&'a self, domain: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        self.lookup(domain).boxed()
+    }
+}
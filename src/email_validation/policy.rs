@@ -0,0 +1,148 @@
+use crate::domain::{SubscriberEmail, SubscriberEmailError};
+use crate::email_validation::{DomainList, MxResolver};
+
+/// Checks on the domain of subscriber emails, beyond their syntax.
+pub struct EmailDomainPolicy {
+    /// Exempt from every other check.
+    allowed: DomainList,
+    denied: DomainList,
+    disposable: DomainList,
+    mx_resolver: Option<Box<dyn MxResolver>>,
+}
+
+impl EmailDomainPolicy {
+    pub fn new(
+        allowed: DomainList,
+        denied: DomainList,
+        disposable: DomainList,
+        mx_resolver: Option<Box<dyn MxResolver>>,
+    ) -> Self {
+        Self {
+            allowed,
+            denied,
+            disposable,
+            mx_resolver,
+        }
+    }
+
+    #[tracing::instrument(name = "Check the domain of a subscriber email", skip(self, email))]
+    pub async fn check(&self, email: &SubscriberEmail) -> Result<(), SubscriberEmailError> {
+        let domain = email
+            .as_ref()
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default();
+        if self.allowed.contains(domain) {
+            return Ok(());
+        }
+        if self.denied.contains(domain) {
+            return Err(SubscriberEmailError::DeniedDomain(domain.to_string()));
+        }
+        if self.disposable.contains(domain) {
+            return Err(SubscriberEmailError::DisposableDomain(domain.to_string()));
+        }
+        if let Some(resolver) = &self.mx_resolver {
+            match resolver.accepts_mail(domain).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(SubscriberEmailError::UndeliverableDomain(
+                        domain.to_string(),
+                    ))
+                }
+                // Our DNS being down is no reason to turn subscribers away.
+                Err(e) => tracing::warn!(
+                    error.cause_chain = ?e,
+                    domain,
+                    "Failed to look up MX records, accepting the email"
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailDomainPolicy;
+    use crate::domain::{SubscriberEmail, SubscriberEmailError};
+    use crate::email_validation::{DomainList, MxResolver};
+    use claims::{assert_err, assert_ok};
+    use futures_util::future::BoxFuture;
+    use futures_util::FutureExt;
+
+    /// Only `example.com` receives email; `broken.example` cannot be looked up.
+    struct StubResolver;
+
+    impl MxResolver for StubResolver {
+        fn accepts_mail<'a>(
+            &'a self,
+            domain: &'a str,
+        ) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+            async move {
+                match domain {
+                    "broken.example" => Err(anyhow::anyhow!("SERVFAIL")),
+                    domain => Ok(domain == "example.com"),
+                }
+            }
+            .boxed()
+        }
+    }
+
+    fn policy() -> EmailDomainPolicy {
+        EmailDomainPolicy::new(
+            DomainList::parse("yopmail.com"),
+            DomainList::parse("competitor.example"),
+            DomainList::bundled_disposable(),
+            Some(Box::new(StubResolver)),
+        )
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn domains_receiving_email_are_accepted() {
+        assert_ok!(policy().check(&email("ursula@example.com")).await);
+    }
+
+    #[tokio::test]
+    async fn denied_and_disposable_domains_are_rejected() {
+        let denied = policy().check(&email("ursula@competitor.example")).await;
+        let disposable = policy().check(&email("ursula@mailinator.com")).await;
+        assert_eq!(
+            denied,
+            Err(SubscriberEmailError::DeniedDomain(
+                "competitor.example".into()
+            ))
+        );
+        assert_eq!(
+            disposable,
+            Err(SubscriberEmailError::DisposableDomain(
+                "mailinator.com".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn domains_without_mail_servers_are_rejected() {
+        let result = policy().check(&email("ursula@no-mail.example")).await;
+        assert_eq!(
+            result,
+            Err(SubscriberEmailError::UndeliverableDomain(
+                "no-mail.example".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn allowed_domains_skip_the_other_checks() {
+        assert_ok!(policy().check(&email("ursula@yopmail.com")).await);
+    }
+
+    #[tokio::test]
+    async fn dns_failures_do_not_reject_the_email() {
+        assert_ok!(policy().check(&email("ursula@broken.example")).await);
+        assert_err!(policy().check(&email("ursula@no-mail.example")).await);
+    }
+}
@@ -1,6 +1,17 @@
+pub mod audit;
+pub mod authentication;
+pub mod bot_protection;
+pub mod cli;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_validation;
+pub mod metrics;
+pub mod openapi;
+pub mod problem_details;
+pub mod rate_limit;
+pub mod redaction;
+pub mod request_id;
 pub mod routes;
 pub mod startup;
 pub mod telemetry;
@@ -1,14 +1,78 @@
+use clap::Parser;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use zero2prod::cli::{
+    create_user_from_stdin, import_subscribers_from_file, send_import_confirmations, Cli, Command,
+    ConfigCommand, Task,
+};
 use zero2prod::configuration::get_configuration;
+use zero2prod::redaction::set_redaction_mode;
 use zero2prod::startup::Application;
-use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::telemetry::{get_subscriber, get_tracer_provider, init_subscriber};
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
-    let subscriber = get_subscriber("zero2prod".to_string(), "info".to_string(), std::io::stdout);
-    init_subscriber(subscriber);
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Task(Task::Serve));
 
-    let configuration = get_configuration().expect("Failed to read configuration.");
-    let application = Application::build(&configuration).await?;
-    application.run_until_stopped().await?;
+    // Reports every invalid setting at once, before anything is started.
+    let configuration = get_configuration()?;
+    let task = match command {
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => {
+            println!("The configuration is valid.");
+            return Ok(());
+        }
+        Command::Task(task) => task,
+    };
+    set_redaction_mode(
+        configuration.logging.pii,
+        &configuration.logging.pii_hash_key,
+    );
+    let tracer_provider = get_tracer_provider("zero2prod", &configuration.tracing)?;
+    // Keep stdout free for the output of one-off commands.
+    let sink = match task {
+        Task::Serve => BoxMakeWriter::new(std::io::stdout),
+        _ => BoxMakeWriter::new(std::io::stderr),
+    };
+    // Dropped at the end of `main`, once the last logs are written.
+    let (subscriber, log_filter, _log_file_guard) = get_subscriber(
+        "zero2prod".to_string(),
+        &configuration.logging,
+        sink,
+        &tracer_provider,
+    )?;
+    init_subscriber(subscriber, log_filter);
+
+    match task {
+        Task::Serve => {
+            let application = Application::build(&configuration).await?;
+            application.run_until_stopped().await?;
+        }
+        Task::ImportSubscribers { file, mode } => {
+            let (report, confirmations) =
+                import_subscribers_from_file(&configuration, &file, mode).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            let sent = send_import_confirmations(&configuration, confirmations).await?;
+            if !sent.unsent_rows.is_empty() {
+                eprintln!(
+                    "No confirmation email could be sent for rows {:?}.",
+                    sent.unsent_rows
+                );
+            }
+        }
+        Task::CreateUser {
+            username,
+            role,
+            email,
+        } => {
+            let user_id = create_user_from_stdin(&configuration, &username, role, email).await?;
+            println!("Created user {username} ({user_id}) with the `{role}` role.");
+        }
+    }
+    // Flushes the spans still waiting to be exported.
+    if let Err(e) = tracer_provider.shutdown() {
+        eprintln!("Failed to export the remaining spans: {e}");
+    }
     Ok(())
 }
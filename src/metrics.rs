@@ -0,0 +1,186 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+/// Email delivery metrics, recorded by the email client itself.
+#[derive(Clone)]
+pub struct EmailMetrics {
+    sent: IntCounterVec,
+    send_duration: HistogramVec,
+}
+
+impl EmailMetrics {
+    pub fn new() -> Self {
+        let sent = IntCounterVec::new(
+            Opts::new("emails_sent_total", "Emails handed to a transport."),
+            &["transport", "outcome"],
+        )
+        .expect("Invalid metric definition.");
+        let send_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "email_send_duration_seconds",
+                "Time taken by a transport to accept an email.",
+            ),
+            &["transport"],
+        )
+        .expect("Invalid metric definition.");
+        Self {
+            sent,
+            send_duration,
+        }
+    }
+
+    pub fn observe(&self, transport: &str, succeeded: bool, elapsed: Duration) {
+        let outcome = if succeeded { "success" } else { "failure" };
+        self.sent.with_label_values(&[transport, outcome]).inc();
+        self.send_duration
+            .with_label_values(&[transport])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+impl Default for EmailMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The Prometheus metrics of an application instance. Cloning is cheap and
+/// clones record into the same metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    pub subscriptions_created: IntCounter,
+    pub subscriptions_confirmed: IntCounter,
+    /// Subscribers removed by an erasure request.
+    pub subscribers_erased: IntCounter,
+    /// Newsletter emails still to be sent by the issues being published.
+    pub delivery_queue_depth: IntGauge,
+}
+
+impl Metrics {
+    pub fn new(email: &EmailMetrics) -> Self {
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to answer HTTP requests.",
+            ),
+            &["method", "route", "status"],
+        )
+        .expect("Invalid metric definition.");
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Connections of the database pool, by state.",
+            ),
+            &["state"],
+        )
+        .expect("Invalid metric definition.");
+        let counter = |name: &str, help: &str| {
+            IntCounter::new(name, help).expect("Invalid metric definition.")
+        };
+        let metrics = Self {
+            registry: Registry::new(),
+            http_request_duration,
+            db_pool_connections,
+            subscriptions_created: counter(
+                "subscriptions_created_total",
+                "Subscriptions created through signups, pending their confirmation.",
+            ),
+            subscriptions_confirmed: counter(
+                "subscriptions_confirmed_total",
+                "Subscriptions confirmed from the link in the welcome email.",
+            ),
+            subscribers_erased: counter(
+                "subscribers_erased_total",
+                "Subscribers removed by an erasure request.",
+            ),
+            delivery_queue_depth: IntGauge::new(
+                "newsletter_delivery_queue_depth",
+                "Newsletter emails still to be sent by the issues being published.",
+            )
+            .expect("Invalid metric definition."),
+        };
+        let collectors: [Box<dyn prometheus::core::Collector>; 8] = [
+            Box::new(metrics.http_request_duration.clone()),
+            Box::new(metrics.db_pool_connections.clone()),
+            Box::new(metrics.subscriptions_created.clone()),
+            Box::new(metrics.subscriptions_confirmed.clone()),
+            Box::new(metrics.subscribers_erased.clone()),
+            Box::new(metrics.delivery_queue_depth.clone()),
+            Box::new(email.sent.clone()),
+            Box::new(email.send_duration.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("Metrics are only registered once.");
+        }
+        metrics
+    }
+
+    /// Pool utilisation is sampled when scraped rather than tracked.
+    fn sample_pool(&self, pool: &PgPool) {
+        let idle = pool.num_idle() as i64;
+        let connections = [
+            ("idle", idle),
+            ("in_use", i64::from(pool.size()) - idle),
+            ("max", i64::from(pool.options().get_max_connections())),
+        ];
+        for (state, count) in connections {
+            self.db_pool_connections
+                .with_label_values(&[state])
+                .set(count);
+        }
+    }
+}
+
+/// Serves the metrics in the Prometheus text format.
+pub async fn metrics_endpoint(
+    metrics: web::Data<Metrics>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    metrics.sample_pool(&pool);
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    if let Err(e) = encoder.encode(&metrics.registry.gather(), &mut body) {
+        tracing::error!(error.cause_chain = ?e, "Failed to encode the metrics");
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(body)
+}
+
+/// Times every request, labelled with the route pattern rather than the
+/// path, so that path parameters do not make up new series.
+pub async fn record_http_metrics(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started_at = Instant::now();
+    let metrics = request.app_data::<web::Data<Metrics>>().cloned();
+    let response = next.call(request).await?;
+    if let Some(metrics) = metrics {
+        let route = response.request().match_pattern();
+        metrics
+            .http_request_duration
+            .with_label_values(&[
+                response.request().method().as_str(),
+                route.as_deref().unwrap_or("unmatched"),
+                response.status().as_str(),
+            ])
+            .observe(started_at.elapsed().as_secs_f64());
+    }
+    Ok(response)
+}
@@ -0,0 +1,90 @@
+use crate::routes;
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// Where version 1 of the JSON API is served.
+pub const API_V1: &str = "/api/v1";
+
+/// The OpenAPI document of version 1 of the API, generated from the
+/// `#[utoipa::path]` attribute of every handler mounted under [`API_V1`].
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zero2prod", version = "1", description = "Newsletter delivery API."),
+    servers((url = "/api/v1")),
+    modifiers(&SecuritySchemes, &NoLicense),
+    paths(
+        routes::subscribe,
+        routes::subscription_form_token,
+        routes::publish_newsletter,
+        routes::import_subscribers_csv,
+        routes::export_subscribers,
+        routes::gdpr_access,
+        routes::gdpr_erasure,
+        routes::get_audit_log,
+        routes::get_log_filter,
+        routes::put_log_filter,
+        routes::login,
+        routes::login_second_factor,
+        routes::logout,
+        routes::enroll_totp,
+        routes::confirm_totp,
+        routes::post_api_key,
+        routes::get_api_keys,
+        routes::delete_api_key,
+        routes::change_password,
+        routes::request_password_reset,
+        routes::reset_password,
+        routes::invite_user,
+        routes::accept_invitation,
+    ),
+    tags(
+        (name = "subscriptions", description = "Joining the newsletter."),
+        (name = "newsletters", description = "Publishing issues."),
+        (name = "subscribers", description = "Managing the subscriber list."),
+        (name = "authentication", description = "Sessions, second factors and passwords."),
+        (name = "api-keys", description = "Keys for scripts and integrations."),
+        (name = "users", description = "Admin accounts."),
+        (name = "audit", description = "The audit log."),
+        (name = "logging", description = "What the service logs."),
+    )
+)]
+pub struct ApiDocV1;
+
+/// Admins authenticate with `Basic` credentials or a session cookie set by
+/// `POST /admin/login`. Integrations send an API key as a `Bearer` token.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "basic",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(
+                crate::authentication::SESSION_COOKIE,
+            ))),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// The package declares no license, which would otherwise be documented as
+/// one with an empty name.
+struct NoLicense;
+
+impl Modify for NoLicense {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.info.license = None;
+    }
+}
+
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDocV1::openapi())
+}
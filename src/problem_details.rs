@@ -0,0 +1,182 @@
+use crate::request_id::RequestId;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, Accept, Header, HeaderMap, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::mime;
+use actix_web::{HttpMessage, HttpRequest};
+use serde_json::{Map, Value};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An RFC 7807 problem: the body of every error response.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The request ID, which is also on every log line of the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Members specific to the error, such as the invalid fields of a form.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl Problem {
+    /// The details of server errors stay in the logs: they are of no use to
+    /// clients and may well reveal how we work inside.
+    fn new(status: StatusCode, detail: Option<String>, request_id: Option<RequestId>) -> Self {
+        Self {
+            problem_type: "about:blank".into(),
+            title: status.canonical_reason().unwrap_or("Error").into(),
+            status: status.as_u16(),
+            detail: detail.filter(|d| !d.is_empty() && !status.is_server_error()),
+            instance: request_id.map(|id| format!("urn:uuid:{id}")),
+            extensions: Map::new(),
+        }
+    }
+
+    /// Error types can add members to the problem by answering with a JSON object.
+    fn extend_with(&mut self, headers: &HeaderMap, body: &[u8]) {
+        if !is_content_type(headers, mime::APPLICATION_JSON.as_ref()) {
+            return;
+        }
+        if let Ok(Value::Object(members)) = serde_json::from_slice(body) {
+            for (name, value) in members {
+                if !matches!(
+                    name.as_str(),
+                    "type" | "title" | "status" | "detail" | "instance"
+                ) {
+                    self.extensions.insert(name, value);
+                }
+            }
+        }
+    }
+
+    fn to_html(&self) -> String {
+        let title = escape_html(&self.title);
+        let detail = self
+            .detail
+            .as_deref()
+            .map(|d| format!("<p>{}</p>", escape_html(d)))
+            .unwrap_or_default();
+        let instance = self
+            .instance
+            .as_deref()
+            .map(|i| format!("<p><small>Request ID: {}</small></p>", escape_html(i)))
+            .unwrap_or_default();
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\">\
+            <title>{} {title}</title></head>\n<body><h1>{title}</h1>{detail}{instance}</body>\n</html>\n",
+            self.status
+        )
+    }
+}
+
+fn is_content_type(headers: &HeaderMap, mime: &str) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(';').next().unwrap_or_default().trim() == mime)
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Browsers get an HTML page, everything else the JSON problem.
+fn prefers_html(request: &HttpRequest) -> bool {
+    Accept::parse(request).is_ok_and(|accept| {
+        let preferred = accept.preference();
+        preferred == mime::TEXT_HTML || preferred.essence_str() == "application/xhtml+xml"
+    })
+}
+
+/// Renders every error response as `application/problem+json`, or as an HTML
+/// page for browsers, whatever the route or extractor that failed. Status
+/// codes and headers, like `Retry-After`, are left as the error set them.
+pub async fn problem_details(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let response = next.call(request).await?.map_into_boxed_body();
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(response);
+    }
+
+    let request_id = response.request().extensions().get::<RequestId>().copied();
+    let html = prefers_html(response.request());
+    let error = response.response().error();
+    match error {
+        Some(e) if status.is_server_error() => {
+            tracing::error!(error.cause_chain = ?e, error.message = %e, "Request failed");
+        }
+        Some(e) => tracing::info!(error.message = %e, "Request rejected"),
+        None => {}
+    }
+    let detail = error.map(|e| e.to_string());
+    Ok(response.map_body(|head, body| {
+        let body = body.try_into_bytes().unwrap_or_default();
+        let detail = detail.or_else(|| {
+            is_content_type(&head.headers, mime::TEXT_PLAIN.as_ref())
+                .then(|| String::from_utf8_lossy(&body).into_owned())
+        });
+        let mut problem = Problem::new(status, detail, request_id);
+        problem.extend_with(&head.headers, &body);
+
+        let (content_type, body) = if html {
+            ("text/html; charset=utf-8", problem.to_html())
+        } else {
+            (
+                PROBLEM_JSON,
+                serde_json::to_string(&problem).expect("A problem is always valid JSON."),
+            )
+        };
+        head.headers.remove(header::CONTENT_LENGTH);
+        head.headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        BoxBody::new(body)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_html, Problem};
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn server_errors_have_no_detail() {
+        let problem = Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Some("error returned from database: relation \"users\" does not exist".into()),
+            None,
+        );
+        assert_eq!(problem.detail, None);
+        assert_eq!(problem.title, "Internal Server Error");
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            escape_html(r#"<script>alert("x")</script>"#),
+            "&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt;"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::configuration::TokenBucketSettings;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+/// A token bucket: every request takes a token, and tokens trickle back in at
+/// a steady rate up to the bucket's capacity.
+#[derive(Clone, Copy, Debug)]
+pub struct Bucket {
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Bucket {
+    pub fn full(settings: &TokenBucketSettings, now: DateTime<Utc>) -> Self {
+        Self {
+            tokens: f64::from(settings.capacity),
+            updated_at: now,
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    pub fn take(
+        &mut self,
+        settings: &TokenBucketSettings,
+        now: DateTime<Utc>,
+    ) -> Result<(), Duration> {
+        let elapsed = (now - self.updated_at).to_std().unwrap_or_default();
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * settings.refill_per_second())
+            .min(f64::from(settings.capacity));
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(time_to_refill(1.0 - self.tokens, settings))
+        }
+    }
+
+    /// When the bucket will be full again, after which it can be forgotten.
+    pub fn refilled_at(&self, settings: &TokenBucketSettings) -> DateTime<Utc> {
+        let missing = f64::from(settings.capacity) - self.tokens;
+        chrono::Duration::from_std(time_to_refill(missing, settings))
+            .ok()
+            .and_then(|d| self.updated_at.checked_add_signed(d))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+fn time_to_refill(tokens: f64, settings: &TokenBucketSettings) -> Duration {
+    // A bucket that never refills stays empty for good.
+    Duration::try_from_secs_f64(tokens.max(0.0) / settings.refill_per_second())
+        .unwrap_or(Duration::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bucket;
+    use crate::configuration::TokenBucketSettings;
+    use chrono::{TimeDelta, Utc};
+    use claims::{assert_err, assert_ok};
+    use std::time::Duration;
+
+    const SETTINGS: TokenBucketSettings = TokenBucketSettings {
+        capacity: 2,
+        refill_per_minute: 6,
+    };
+
+    #[test]
+    fn a_full_bucket_allows_a_burst_up_to_its_capacity() {
+        let now = Utc::now();
+        let mut bucket = Bucket::full(&SETTINGS, now);
+        assert_ok!(bucket.take(&SETTINGS, now));
+        assert_ok!(bucket.take(&SETTINGS, now));
+        assert_eq!(bucket.take(&SETTINGS, now), Err(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn tokens_are_refilled_over_time() {
+        let now = Utc::now();
+        let mut bucket = Bucket::full(&SETTINGS, now);
+        bucket.take(&SETTINGS, now).unwrap();
+        bucket.take(&SETTINGS, now).unwrap();
+
+        assert_err!(bucket.take(&SETTINGS, now + TimeDelta::seconds(5)));
+        assert_ok!(bucket.take(&SETTINGS, now + TimeDelta::seconds(10)));
+    }
+
+    #[test]
+    fn tokens_do_not_pile_up_beyond_the_capacity() {
+        let now = Utc::now();
+        let mut bucket = Bucket::full(&SETTINGS, now);
+        let later = now + TimeDelta::hours(1);
+        for _ in 0..2 {
+            assert_ok!(bucket.take(&SETTINGS, later));
+        }
+        assert_err!(bucket.take(&SETTINGS, later));
+        assert_eq!(
+            bucket.refilled_at(&SETTINGS),
+            later + TimeDelta::seconds(20)
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::net::IpAddr;
+
+/// The address of the client behind `peer`, as reported by trusted proxies.
+///
+/// `X-Forwarded-For` is read from right to left: every proxy appends the
+/// address it received the request from, so the first address that is not a
+/// trusted proxy is the client. Anything further left may have been forged.
+pub fn resolve_client_ip<'a>(
+    peer: IpAddr,
+    forwarded_for: impl DoubleEndedIterator<Item = &'a str>,
+    trusted_proxies: &[IpAddr],
+) -> IpAddr {
+    let mut client = peer;
+    if !trusted_proxies.contains(&client) {
+        return client;
+    }
+    for hop in forwarded_for.rev().flat_map(|value| value.rsplit(',')) {
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_client_ip;
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn the_header_is_ignored_from_untrusted_peers() {
+        let client = resolve_client_ip(ip("203.0.113.7"), ["198.51.100.1"].into_iter(), &[]);
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_rightmost_untrusted_address_is_the_client() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let client = resolve_client_ip(
+            ip("10.0.0.1"),
+            ["198.51.100.1, 203.0.113.7", "10.0.0.2"].into_iter(),
+            &proxies,
+        );
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn a_garbled_header_stops_at_the_last_trusted_hop() {
+        let proxies = [ip("10.0.0.1")];
+        let client = resolve_client_ip(ip("10.0.0.1"), ["unknown"].into_iter(), &proxies);
+        assert_eq!(client, ip("10.0.0.1"));
+    }
+}
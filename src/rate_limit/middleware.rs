@@ -0,0 +1,166 @@
+use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+use crate::domain::EmailCanonicalizer;
+use crate::rate_limit::{resolve_client_ip, RateLimitStore};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError {
+    #[error("Too many requests. Try again later.")]
+    TooManyRequests(Duration),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for RateLimitError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RateLimitError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            RateLimitError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            RateLimitError::TooManyRequests(retry_after) => response
+                .insert_header((header::RETRY_AFTER, retry_after_seconds(*retry_after)))
+                .body(self.to_string()),
+            RateLimitError::UnexpectedError(_) => response.finish(),
+        }
+    }
+}
+
+/// `Retry-After` only has a resolution of seconds: round up so that clients
+/// honouring it do not come back a moment too early.
+pub fn retry_after_seconds(retry_after: Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
+/// Token-bucket rate limits per client IP and per target email address.
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    store: RateLimitStore,
+    /// Aliases of a mailbox share its limit.
+    canonicalizer: EmailCanonicalizer,
+}
+
+impl RateLimiter {
+    pub fn new(
+        settings: RateLimitSettings,
+        store: RateLimitStore,
+        canonicalizer: EmailCanonicalizer,
+    ) -> Self {
+        Self {
+            settings,
+            store,
+            canonicalizer,
+        }
+    }
+
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let peer = request.peer_addr()?.ip();
+        let forwarded_for = request
+            .headers()
+            .get_all(header::X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>();
+        Some(resolve_client_ip(
+            peer,
+            forwarded_for.into_iter(),
+            &self.settings.trusted_proxies,
+        ))
+    }
+
+    #[tracing::instrument(name = "Apply rate limits", skip(self, email))]
+    pub async fn check(
+        &self,
+        client_ip: Option<IpAddr>,
+        email: Option<&str>,
+    ) -> Result<(), RateLimitError> {
+        if let Some(client_ip) = client_ip {
+            self.take(&format!("ip:{client_ip}"), &self.settings.per_ip)
+                .await?;
+        }
+        if let Some(email) = email {
+            // Keys are hashed so that the store never holds email addresses.
+            let digest = Sha256::digest(self.canonicalizer.canonical_email(email));
+            self.take(
+                &format!("email:{}", hex::encode(digest)),
+                &self.settings.per_email,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn take(&self, key: &str, settings: &TokenBucketSettings) -> Result<(), RateLimitError> {
+        self.store
+            .take(key, settings)
+            .await
+            .context("Failed to apply a rate limit.")?
+            .map_err(|retry_after| {
+                tracing::warn!(
+                    target: "security",
+                    limit = key.split(':').next(),
+                    retry_after_seconds = retry_after_seconds(retry_after),
+                    "Rate limit exceeded"
+                );
+                RateLimitError::TooManyRequests(retry_after)
+            })
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct EmailField {
+    email: Option<String>,
+}
+
+/// Reads the `email` field of a form or JSON body without consuming it for the handler.
+async fn target_email(request: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    let parse: fn(&[u8]) -> Option<EmailField> = match request.content_type() {
+        "application/x-www-form-urlencoded" => |body| serde_urlencoded::from_bytes(body).ok(),
+        "application/json" => |body| serde_json::from_slice(body).ok(),
+        _ => return Ok(None),
+    };
+    let body = request.extract::<web::Bytes>().await?;
+    let email = parse(&body).and_then(|field| field.email);
+    request.set_payload(Payload::from(body));
+    Ok(email)
+}
+
+async fn admit(request: &mut ServiceRequest) -> Result<(), actix_web::Error> {
+    let limiter = request
+        .app_data::<web::Data<RateLimiter>>()
+        .cloned()
+        .context("The rate limiter is not registered as app data.")
+        .map_err(RateLimitError::UnexpectedError)?;
+    let client_ip = limiter.client_ip(request.request());
+    let email = target_email(request).await?;
+    limiter.check(client_ip, email.as_deref()).await?;
+    Ok(())
+}
+
+/// Rejects requests over the limits of the [`RateLimiter`] registered as app data.
+///
+/// Rejections are answered here rather than returned as errors, so that the
+/// middleware wrapping the app sees them like any other error response.
+pub async fn rate_limit(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Err(e) = admit(&mut request).await {
+        return Ok(request.error_response(e).map_into_right_body());
+    }
+    next.call(request)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
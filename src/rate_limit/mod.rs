@@ -0,0 +1,9 @@
+mod bucket;
+mod client_ip;
+mod middleware;
+mod store;
+
+pub use bucket::*;
+pub use client_ip::*;
+pub use middleware::*;
+pub use store::*;
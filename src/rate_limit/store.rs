@@ -0,0 +1,146 @@
+use crate::configuration::TokenBucketSettings;
+use crate::rate_limit::Bucket;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Full buckets are only swept once the map grows past this size.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// How often full buckets are deleted from Postgres.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct MemoryEntry {
+    bucket: Bucket,
+    refilled_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct MemoryStore(Mutex<HashMap<String, MemoryEntry>>);
+
+/// Where the token buckets are kept.
+pub enum RateLimitStore {
+    Memory(MemoryStore),
+    Postgres(PgPool),
+}
+
+impl RateLimitStore {
+    /// Takes a token from the bucket under `key`, or returns how long until one is available.
+    pub async fn take(
+        &self,
+        key: &str,
+        settings: &TokenBucketSettings,
+    ) -> Result<Result<(), Duration>, anyhow::Error> {
+        let now = Utc::now();
+        match self {
+            Self::Memory(store) => Ok(take_in_memory(store, key, settings, now)),
+            Self::Postgres(pool) => take_in_postgres(pool, key, settings, now).await,
+        }
+    }
+}
+
+fn take_in_memory(
+    store: &MemoryStore,
+    key: &str,
+    settings: &TokenBucketSettings,
+    now: DateTime<Utc>,
+) -> Result<(), Duration> {
+    let mut buckets = store.0.lock().unwrap();
+    if buckets.len() > SWEEP_THRESHOLD {
+        buckets.retain(|_, entry| entry.refilled_at > now);
+    }
+    let entry = buckets
+        .entry(key.to_string())
+        .or_insert_with(|| MemoryEntry {
+            bucket: Bucket::full(settings, now),
+            refilled_at: now,
+        });
+    let result = entry.bucket.take(settings, now);
+    entry.refilled_at = entry.bucket.refilled_at(settings);
+    result
+}
+
+#[tracing::instrument(name = "Take a rate limit token from Postgres", skip(pool, settings))]
+async fn take_in_postgres(
+    pool: &PgPool,
+    key: &str,
+    settings: &TokenBucketSettings,
+    now: DateTime<Utc>,
+) -> Result<Result<(), Duration>, anyhow::Error> {
+    // Refilling the bucket and taking a token happen in a single statement,
+    // under the row lock of the upsert, so concurrent requests for a key queue
+    // up behind each other. A bucket out of tokens is left untouched and no row
+    // comes back. Each token taken pushes back when the bucket is full again by
+    // the time it takes to refill one.
+    let taken = sqlx::query_scalar!(
+        r#"INSERT INTO rate_limit_buckets (key, tokens, updated_at, refilled_at)
+        SELECT $1, $2::DOUBLE PRECISION - 1, $4,
+            COALESCE($4::TIMESTAMPTZ + make_interval(secs => 1 / NULLIF($3::DOUBLE PRECISION, 0)), 'infinity')
+        WHERE $2 >= 1
+        ON CONFLICT (key) DO UPDATE SET
+            tokens = LEAST(
+                $2,
+                rate_limit_buckets.tokens
+                    + $3 * GREATEST(0, EXTRACT(EPOCH FROM $4 - rate_limit_buckets.updated_at)::DOUBLE PRECISION)
+            ) - 1,
+            updated_at = $4,
+            refilled_at = COALESCE(
+                GREATEST(rate_limit_buckets.refilled_at, $4) + make_interval(secs => 1 / NULLIF($3, 0)),
+                'infinity'
+            )
+        WHERE LEAST(
+            $2,
+            rate_limit_buckets.tokens
+                + $3 * GREATEST(0, EXTRACT(EPOCH FROM $4 - rate_limit_buckets.updated_at)::DOUBLE PRECISION)
+        ) >= 1
+        RETURNING key"#,
+        key,
+        f64::from(settings.capacity),
+        settings.refill_per_second(),
+        now,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to take a rate limit token.")?;
+    if taken.is_some() {
+        return Ok(Ok(()));
+    }
+    let mut bucket = sqlx::query!(
+        "SELECT tokens, updated_at FROM rate_limit_buckets WHERE key = $1",
+        key
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch a rate limit bucket.")?
+    .map_or(Bucket::full(settings, now), |row| Bucket {
+        tokens: row.tokens,
+        updated_at: row.updated_at,
+    });
+    // Only the wait until the next token is left to work out. A token that
+    // trickled in since the upsert is not handed out: the client retries.
+    Ok(Err(bucket.take(settings, now).err().unwrap_or_default()))
+}
+
+/// Deletes the Postgres buckets that are full again every [`SWEEP_INTERVAL`]:
+/// they carry no information. Runs until the runtime shuts down.
+pub async fn sweep_full_buckets(pool: PgPool) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = delete_full_buckets(&pool).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to sweep the rate limit buckets");
+        }
+    }
+}
+
+#[tracing::instrument(name = "Sweep full rate limit buckets", skip(pool))]
+async fn delete_full_buckets(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!("DELETE FROM rate_limit_buckets WHERE refilled_at <= now()")
+        .execute(pool)
+        .await
+        .context("Failed to delete full rate limit buckets.")?;
+    Ok(())
+}
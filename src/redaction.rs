@@ -0,0 +1,175 @@
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, SecretBox};
+use sha2::Sha256;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// How personal data is written to logs and spans.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionMode {
+    /// As is: for local development only.
+    Full,
+    /// A short HMAC-SHA256 digest, keyed with `logging.pii_hash_key`: the same
+    /// value always gets the same digest, so the entries about one subscriber
+    /// can still be told apart, and searched for by those holding the key.
+    Hashed,
+    /// The first character, and the domain of email addresses.
+    Masked,
+}
+
+static MODE: OnceLock<RedactionMode> = OnceLock::new();
+static HASH_KEY: OnceLock<SecretBox<String>> = OnceLock::new();
+
+/// Sets the redaction mode of the process and the key of its hashes. They can
+/// only be set once, before anything is logged: later calls are ignored.
+pub fn set_redaction_mode(mode: RedactionMode, hash_key: &SecretBox<String>) {
+    let _ = MODE.set(mode);
+    let _ = HASH_KEY.set(SecretBox::new(Box::new(hash_key.expose_secret().clone())));
+}
+
+/// Random unless configured: plain hashes of addresses would give them back to
+/// anyone hashing a list of candidates.
+fn hash_key() -> &'static SecretBox<String> {
+    HASH_KEY.get_or_init(|| {
+        let mut rng = thread_rng();
+        let key: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect();
+        SecretBox::new(Box::new(key))
+    })
+}
+
+/// Hashed unless configured otherwise, so that nothing leaks by default.
+pub fn redaction_mode() -> RedactionMode {
+    MODE.get().copied().unwrap_or(RedactionMode::Hashed)
+}
+
+/// Personal data, formatted according to the redaction mode when displayed
+/// or debugged. Record it in spans and error messages with `%Redacted(value)`.
+pub struct Redacted<T>(pub T);
+
+impl<T: AsRef<str>> Redacted<T> {
+    pub fn with_mode(&self, mode: RedactionMode) -> String {
+        let value = self.0.as_ref();
+        match mode {
+            RedactionMode::Full => value.to_owned(),
+            RedactionMode::Hashed => hashed(value, hash_key().expose_secret()),
+            RedactionMode::Masked => match value.rsplit_once('@') {
+                Some((local, domain)) => format!("{}@{domain}", mask(local)),
+                None => mask(value),
+            },
+        }
+    }
+}
+
+fn hashed(value: &str, key: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length.");
+    mac.update(value.as_bytes());
+    let digest = hex::encode(mac.finalize().into_bytes());
+    format!("sha256:{}", &digest[..16])
+}
+
+fn mask(s: &str) -> String {
+    match s.chars().next() {
+        Some(first) => format!("{first}***"),
+        None => String::new(),
+    }
+}
+
+impl<T: AsRef<str>> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.with_mode(redaction_mode()))
+    }
+}
+
+impl<T: AsRef<str>> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.with_mode(redaction_mode()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hashed, Redacted, RedactionMode};
+    use claims::assert_none;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn full_mode_keeps_the_value() {
+        let redacted = Redacted("ursula@example.com").with_mode(RedactionMode::Full);
+        assert_eq!(redacted, "ursula@example.com");
+    }
+
+    #[test]
+    fn masked_mode_keeps_the_first_character_and_the_domain() {
+        let email = Redacted("ursula@example.com").with_mode(RedactionMode::Masked);
+        assert_eq!(email, "u***@example.com");
+        let name = Redacted("Ursula Le Guin").with_mode(RedactionMode::Masked);
+        assert_eq!(name, "U***");
+        assert_eq!(Redacted("").with_mode(RedactionMode::Masked), "");
+    }
+
+    #[test]
+    fn hashed_mode_is_stable_and_hides_the_value() {
+        let first = Redacted("ursula@example.com").with_mode(RedactionMode::Hashed);
+        let second = Redacted("ursula@example.com".to_string()).with_mode(RedactionMode::Hashed);
+        let other = Redacted("le_guin@example.com").with_mode(RedactionMode::Hashed);
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert!(first.starts_with("sha256:"));
+        assert!(!first.contains("ursula"));
+    }
+
+    #[test]
+    fn hashes_depend_on_the_key_of_the_process() {
+        let one_process = hashed("ursula@example.com", "one-key");
+        let another_process = hashed("ursula@example.com", "another-key");
+        assert_ne!(one_process, another_process);
+        assert_eq!(one_process, hashed("ursula@example.com", "one-key"));
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn span_fields_are_redacted_in_the_logs() {
+        let buffer = Buffer::default();
+        let sink = buffer.clone();
+        let subscriber =
+            tracing_subscriber::registry()
+                .with(JsonStorageLayer)
+                .with(BunyanFormattingLayer::new("test".into(), move || {
+                    sink.clone()
+                }));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "Adding a new subscriber.",
+                subscriber_email = %Redacted("ursula@example.com")
+            );
+            span.in_scope(|| tracing::info!("Stored"));
+        });
+
+        let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("subscriber_email"));
+        assert_none!(logs.find("ursula"));
+    }
+}
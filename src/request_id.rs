@@ -0,0 +1,138 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use std::future::{ready, Future, Ready};
+use tracing::Span;
+use tracing_actix_web::{root_span, DefaultRootSpanBuilder, RootSpanBuilder};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// Identifies a request in the logs, the audit log, error responses and the
+/// calls made to the email provider while handling it.
+///
+/// Taken from the `X-Request-Id` header when a client or proxy sends a UUID,
+/// so that their logs and ours can be joined. Generated otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestId(Uuid);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = headers.get(REQUEST_ID_HEADER)?.to_str().ok()?;
+        Uuid::try_parse(value.trim()).ok().map(Self)
+    }
+
+    /// The ID of the request being handled by the current task, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|id| *id).ok()
+    }
+
+    /// Runs `future` as part of the request, e.g. once spawned off it.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(req.extensions().get::<RequestId>().copied().ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError(
+                "The request ID middleware is not registered.",
+            )
+        }))
+    }
+}
+
+/// Assigns the request its ID and echoes it in the `X-Request-Id` header of
+/// the response. Registered outside of [`TracingLogger`](tracing_actix_web::TracingLogger),
+/// so that the root span is created with it.
+pub async fn request_id(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = RequestId::from_headers(request.headers()).unwrap_or_else(RequestId::generate);
+    request.extensions_mut().insert(id);
+    let mut response = CURRENT.scope(id, next.call(request)).await?;
+    let value = HeaderValue::from_str(&id.to_string()).expect("UUIDs are valid header values.");
+    response
+        .headers_mut()
+        .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    Ok(response)
+}
+
+/// The default root span, with our request ID rather than the one generated
+/// by `tracing-actix-web`.
+pub struct RequestIdRootSpanBuilder;
+
+impl RootSpanBuilder for RequestIdRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let span = root_span!(request);
+        if let Some(id) = request.extensions().get::<RequestId>() {
+            span.record("request_id", tracing::field::display(id));
+        }
+        span
+    }
+
+    fn on_request_end<B: MessageBody>(
+        span: Span,
+        outcome: &Result<ServiceResponse<B>, actix_web::Error>,
+    ) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequestId, REQUEST_ID_HEADER};
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use claims::{assert_none, assert_some_eq};
+    use uuid::Uuid;
+
+    fn headers(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            HeaderValue::from_static(value),
+        );
+        headers
+    }
+
+    #[test]
+    fn uuids_are_accepted_in_any_of_their_formats() {
+        let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        let formats = [
+            "67e55044-10b1-426f-9247-bb680e5fe0c8",
+            "67e5504410b1426f9247bb680e5fe0c8",
+            " urn:uuid:67E55044-10B1-426F-9247-BB680E5FE0C8 ",
+        ];
+        for format in formats {
+            assert_some_eq!(RequestId::from_headers(&headers(format)), RequestId(id));
+        }
+    }
+
+    #[test]
+    fn other_ids_are_ignored() {
+        assert_none!(RequestId::from_headers(&headers("abc")));
+        assert_none!(RequestId::from_headers(&headers("")));
+        assert_none!(RequestId::from_headers(&HeaderMap::new()));
+    }
+}
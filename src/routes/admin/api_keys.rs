@@ -0,0 +1,200 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::ApiKey;
+use crate::authentication::{
+    create_api_key, list_api_keys, revoke_api_key, Authorized, CanManageApiKeys, Scope,
+};
+use crate::problem_details::Problem;
+use crate::request_id::RequestId;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiKeyError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no active API key with this id.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ApiKeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiKeyError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiKeyError::NotFound => StatusCode::NOT_FOUND,
+            ApiKeyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct NewApiKey {
+    name: String,
+    scopes: Vec<Scope>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct CreatedApiKey {
+    id: Uuid,
+    /// Only ever returned here: the database keeps a hash.
+    key: String,
+    name: String,
+    scopes: Vec<Scope>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Creates an API key. The key is only ever shown in this response.
+#[utoipa::path(
+    post,
+    path = "/admin/api-keys",
+    tag = "api-keys",
+    request_body = NewApiKey,
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 201, description = "Created.", body = CreatedApiKey),
+        (status = 400, description = "Invalid request.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Only admins can do this, and not with an API key.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Create an API key",
+    skip(user, request_id, body, pool),
+    fields(username = %user.username, name = %body.name)
+)]
+pub async fn post_api_key(
+    user: Authorized<CanManageApiKeys>,
+    request_id: RequestId,
+    body: web::Json<NewApiKey>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiKeyError> {
+    let NewApiKey {
+        name,
+        mut scopes,
+        expires_at,
+    } = body.into_inner();
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiKeyError::ValidationError(
+            "The name must not be empty.".into(),
+        ));
+    }
+    scopes.sort_unstable();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(ApiKeyError::ValidationError(
+            "At least one scope is required.".into(),
+        ));
+    }
+    if expires_at.is_some_and(|t| t <= Utc::now()) {
+        return Err(ApiKeyError::ValidationError(
+            "The expiry date must be in the future.".into(),
+        ));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let (id, key) =
+        create_api_key(&mut *transaction, &name, &scopes, user.user_id, expires_at).await?;
+    let event = AuditEvent::new(&user.username, "api_key.create")
+        .target(id.to_string())
+        .request_id(request_id)
+        .after(serde_json::json!({
+            "name": name,
+            "scopes": scopes,
+            "expires_at": expires_at,
+        }));
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the new API key in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create an API key.")?;
+    Ok(HttpResponse::Created().json(CreatedApiKey {
+        id,
+        key,
+        name,
+        scopes,
+        expires_at,
+    }))
+}
+
+/// Lists every API key, without the keys themselves.
+#[utoipa::path(
+    get,
+    path = "/admin/api-keys",
+    tag = "api-keys",
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 200, description = "OK.", body = Vec<ApiKey>),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Only admins can do this, and not with an API key.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "List API keys",
+    skip(user, pool),
+    fields(username = %user.username)
+)]
+pub async fn get_api_keys(
+    user: Authorized<CanManageApiKeys>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiKeyError> {
+    let keys = list_api_keys(&pool).await?;
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+/// Revokes an API key.
+#[utoipa::path(
+    delete,
+    path = "/admin/api-keys/{id}",
+    tag = "api-keys",
+    params(("id" = Uuid, Path)),
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 204, description = "Revoked."),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Only admins can do this, and not with an API key.", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "No API key with this id.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Revoke an API key",
+    skip(user, request_id, pool),
+    fields(username = %user.username)
+)]
+pub async fn delete_api_key(
+    user: Authorized<CanManageApiKeys>,
+    request_id: RequestId,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiKeyError> {
+    let id = id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if !revoke_api_key(&mut *transaction, id).await? {
+        return Err(ApiKeyError::NotFound);
+    }
+    let event = AuditEvent::new(&user.username, "api_key.revoke")
+        .target(id.to_string())
+        .request_id(request_id);
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the revocation in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to revoke an API key.")?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,124 @@
+use crate::authentication::{Authorized, CanReadAuditLog};
+use crate::problem_details::Problem;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, QueryBuilder};
+
+const MAX_AUDIT_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditLogError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for AuditLogError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuditLogError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            AuditLogError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+#[derive(serde::Deserialize, Debug, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogFilters {
+    actor: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    request_id: Option<String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    /// Only return entries older than this id, to page through the log.
+    before_id: Option<i64>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+}
+
+#[derive(serde::Serialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct AuditLogEntry {
+    id: i64,
+    occurred_at: DateTime<Utc>,
+    actor: String,
+    action: String,
+    target: Option<String>,
+    request_id: Option<String>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+/// Lists audit log entries, newest first.
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "audit",
+    params(AuditLogFilters),
+    security(("basic" = []), ("session" = []), ("api_key" = [])),
+    responses(
+        (status = 200, description = "OK.", body = Vec<AuditLogEntry>),
+        (status = 400, description = "The page size is out of bounds.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The role or the scopes of the API key do not allow it.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Read the audit log",
+    skip(user, pool),
+    fields(username = %user.username)
+)]
+pub async fn get_audit_log(
+    user: Authorized<CanReadAuditLog>,
+    filters: web::Query<AuditLogFilters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AuditLogError> {
+    if !(1..=MAX_AUDIT_PAGE_SIZE).contains(&filters.limit) {
+        return Err(AuditLogError::ValidationError(format!(
+            "`limit` must be between 1 and {MAX_AUDIT_PAGE_SIZE}."
+        )));
+    }
+
+    let mut query = QueryBuilder::new(
+        "SELECT id, occurred_at, actor, action, target, request_id, before, after \
+        FROM audit_log WHERE TRUE",
+    );
+    if let Some(actor) = &filters.actor {
+        query.push(" AND actor = ").push_bind(actor);
+    }
+    if let Some(action) = &filters.action {
+        query.push(" AND action = ").push_bind(action);
+    }
+    if let Some(target) = &filters.target {
+        query.push(" AND target = ").push_bind(target);
+    }
+    if let Some(request_id) = &filters.request_id {
+        query.push(" AND request_id = ").push_bind(request_id);
+    }
+    if let Some(since) = filters.since {
+        query.push(" AND occurred_at >= ").push_bind(since);
+    }
+    if let Some(until) = filters.until {
+        query.push(" AND occurred_at < ").push_bind(until);
+    }
+    if let Some(before_id) = filters.before_id {
+        query.push(" AND id < ").push_bind(before_id);
+    }
+    query
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(filters.limit);
+
+    let entries = query
+        .build_query_as::<AuditLogEntry>()
+        .fetch_all(pool.get_ref())
+        .await
+        .context("Failed to read the audit log.")?;
+    Ok(HttpResponse::Ok().json(entries))
+}
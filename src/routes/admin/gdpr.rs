@@ -0,0 +1,268 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{Authorized, CanEditSubscribers, CanReadSubscribers};
+use crate::configuration::GdprSettings;
+use crate::domain::EmailCanonicalizer;
+use crate::metrics::Metrics;
+use crate::problem_details::Problem;
+use crate::request_id::RequestId;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretBox};
+use sha2::Sha256;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GdprError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for GdprError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            GdprError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct DataSubjectRequest {
+    email: String,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct StoredSubscription {
+    id: Uuid,
+    email: String,
+    email_canonical: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    status: String,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct StoredSubscriptionToken {
+    subscription_token: String,
+    subscriber_id: Uuid,
+}
+
+/// Everything stored about a data subject, grouped by table.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct DataSubjectReport {
+    email: String,
+    subscriptions: Vec<StoredSubscription>,
+    subscription_tokens: Vec<StoredSubscriptionToken>,
+}
+
+#[derive(serde::Serialize, Debug, utoipa::ToSchema)]
+pub struct ErasureReport {
+    erased_subscriptions: u64,
+    erased_subscription_tokens: u64,
+}
+
+/// Identifies addresses in `erased_subscribers` without storing them.
+///
+/// The hashes are keyed: plain hashes of the addresses would give them back
+/// to anyone hashing a list of candidates.
+pub struct ErasureKey {
+    key: SecretBox<String>,
+    /// Aliases of a mailbox share its hash.
+    canonicalizer: EmailCanonicalizer,
+}
+
+impl ErasureKey {
+    pub fn new(settings: &GdprSettings, canonicalizer: EmailCanonicalizer) -> Self {
+        let key = settings.erasure_key.expose_secret().clone();
+        Self {
+            key: SecretBox::new(Box::new(key)),
+            canonicalizer,
+        }
+    }
+
+    /// Hash identifying an address in `erased_subscribers`.
+    pub fn hash(&self, email: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        mac.update(self.canonicalizer.canonical_email(email).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Reports everything stored about an email address.
+#[utoipa::path(
+    post,
+    path = "/admin/gdpr/access",
+    tag = "subscribers",
+    request_body = DataSubjectRequest,
+    security(("basic" = []), ("session" = []), ("api_key" = [])),
+    responses(
+        (status = 200, description = "OK.", body = DataSubjectReport),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The role or the scopes of the API key do not allow it.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Handle a data subject access request",
+    skip(user, body, pool, canonicalizer),
+    fields(username = %user.username)
+)]
+pub async fn gdpr_access(
+    user: Authorized<CanReadSubscribers>,
+    body: web::Json<DataSubjectRequest>,
+    pool: web::Data<PgPool>,
+    canonicalizer: web::Data<EmailCanonicalizer>,
+) -> Result<HttpResponse, GdprError> {
+    let subscriptions = sqlx::query_as!(
+        StoredSubscription,
+        r#"SELECT id, email, email_canonical, name, subscribed_at, status FROM subscriptions
+        WHERE email_canonical = $1"#,
+        canonicalizer.canonical_email(&body.email),
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to fetch the subscriptions of a data subject.")?;
+    let subscriber_ids: Vec<Uuid> = subscriptions.iter().map(|s| s.id).collect();
+    let subscription_tokens = sqlx::query_as!(
+        StoredSubscriptionToken,
+        r#"SELECT subscription_token, subscriber_id FROM subscription_tokens
+        WHERE subscriber_id = ANY($1)"#,
+        &subscriber_ids,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to fetch the subscription tokens of a data subject.")?;
+
+    Ok(HttpResponse::Ok().json(DataSubjectReport {
+        email: body.0.email,
+        subscriptions,
+        subscription_tokens,
+    }))
+}
+
+/// Erases everything stored about an email address and leaves a tombstone,
+/// so that imports do not bring it back. The address can still subscribe
+/// again, since that takes its owner confirming from the email.
+#[utoipa::path(
+    post,
+    path = "/admin/gdpr/erasure",
+    tag = "subscribers",
+    request_body = DataSubjectRequest,
+    security(("basic" = []), ("session" = []), ("api_key" = [])),
+    responses(
+        (status = 200, description = "OK.", body = ErasureReport),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The role or the scopes of the API key do not allow it.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Erase a data subject",
+    skip(user, request_id, body, pool, erasure_key, canonicalizer, metrics),
+    fields(username = %user.username)
+)]
+pub async fn gdpr_erasure(
+    user: Authorized<CanEditSubscribers>,
+    request_id: RequestId,
+    body: web::Json<DataSubjectRequest>,
+    pool: web::Data<PgPool>,
+    erasure_key: web::Data<ErasureKey>,
+    canonicalizer: web::Data<EmailCanonicalizer>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, GdprError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let report = erase_subscriber(
+        &mut transaction,
+        &canonicalizer.canonical_email(&body.email),
+    )
+    .await
+    .context("Failed to erase the data of a data subject.")?;
+    let email_hash = erasure_key.hash(&body.email);
+    store_tombstone(
+        &mut transaction,
+        &email_hash,
+        &user.username,
+        report.erased_subscriptions,
+    )
+    .await
+    .context("Failed to store the erasure tombstone.")?;
+    // The erased data itself must not end up in the audit log, only how much of it there was.
+    let event = AuditEvent::new(&user.username, "subscriber.erase")
+        .target(email_hash)
+        .request_id(request_id)
+        .before(serde_json::json!(report));
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the erasure in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a data subject.")?;
+    metrics
+        .subscribers_erased
+        .inc_by(report.erased_subscriptions);
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[tracing::instrument(name = "Delete the stored data of a subscriber", skip_all)]
+async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_canonical: &str,
+) -> Result<ErasureReport, sqlx::Error> {
+    let subscriber_ids: Vec<Uuid> = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE email_canonical = $1 FOR UPDATE",
+        email_canonical
+    )
+    .fetch_all(&mut **transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    let erased_subscription_tokens = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)",
+            &subscriber_ids
+        ))
+        .await?
+        .rows_affected();
+    let erased_subscriptions = transaction
+        .execute(sqlx::query!(
+            "DELETE FROM subscriptions WHERE id = ANY($1)",
+            &subscriber_ids
+        ))
+        .await?
+        .rows_affected();
+    Ok(ErasureReport {
+        erased_subscriptions,
+        erased_subscription_tokens,
+    })
+}
+
+#[tracing::instrument(name = "Store an erasure tombstone", skip(transaction, email_hash))]
+async fn store_tombstone(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_hash: &str,
+    erased_by: &str,
+    erased_subscriptions: u64,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"INSERT INTO erased_subscribers (email_hash, erased_at, erased_by, erased_subscriptions)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (email_hash) DO UPDATE
+        SET erased_at = EXCLUDED.erased_at,
+            erased_by = EXCLUDED.erased_by,
+            erased_subscriptions = erased_subscribers.erased_subscriptions + EXCLUDED.erased_subscriptions
+        "#,
+        email_hash,
+        Utc::now(),
+        erased_by,
+        erased_subscriptions as i32,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
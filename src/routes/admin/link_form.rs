@@ -0,0 +1,32 @@
+use crate::problem_details::escape_html;
+use actix_web::http::header::{self, ContentType};
+use actix_web::HttpResponse;
+
+/// The `token` query parameter of the links sent by email.
+#[derive(serde::Deserialize)]
+pub struct LinkToken {
+    token: String,
+}
+
+impl LinkToken {
+    /// A page with a form posting the token to `action`, along with the
+    /// `fields` the user fills in.
+    ///
+    /// Opening the link changes nothing, so that mail scanners following it
+    /// do not use the token up.
+    pub fn form_page(&self, title: &str, action: &str, fields: &str) -> HttpResponse {
+        let body = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\">\
+            <title>{title}</title></head>\n<body><h1>{title}</h1>\n\
+            <form action=\"{action}\" method=\"post\">\
+            <input type=\"hidden\" name=\"token\" value=\"{}\">{fields}\
+            <button type=\"submit\">{title}</button></form>\n</body>\n</html>\n",
+            escape_html(&self.token)
+        );
+        HttpResponse::Ok()
+            .content_type(ContentType::html())
+            // Keeps the token out of the `Referer` header.
+            .insert_header((header::REFERRER_POLICY, "no-referrer"))
+            .body(body)
+    }
+}
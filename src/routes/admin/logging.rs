@@ -0,0 +1,114 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{Authorized, CanConfigureLogging};
+use crate::problem_details::Problem;
+use crate::request_id::RequestId;
+use crate::telemetry::{log_filter, LogFilter};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogFilterError {
+    #[error("Invalid filter directives: {0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for LogFilterError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LogFilterError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            LogFilterError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Filter directives, in the syntax of `RUST_LOG`.
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LogFilterBody {
+    #[schema(example = "info,sqlx=warn")]
+    filter: String,
+}
+
+fn installed_filter() -> Result<&'static LogFilter, anyhow::Error> {
+    log_filter().context("No log filter was installed.")
+}
+
+/// Reads the log filter in effect.
+#[utoipa::path(
+    get,
+    path = "/admin/log-filter",
+    tag = "logging",
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 200, description = "OK.", body = LogFilterBody),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Only admins can do this, and not with an API key.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Read the log filter",
+    skip(user),
+    fields(username = %user.username)
+)]
+pub async fn get_log_filter(
+    user: Authorized<CanConfigureLogging>,
+) -> Result<HttpResponse, LogFilterError> {
+    let filter = installed_filter()?
+        .current()
+        .context("Failed to read the log filter.")?;
+    Ok(HttpResponse::Ok().json(LogFilterBody { filter }))
+}
+
+/// Changes the log filter until the next restart, e.g. to turn on debug
+/// logs for one module while investigating an incident.
+#[utoipa::path(
+    put,
+    path = "/admin/log-filter",
+    tag = "logging",
+    request_body = LogFilterBody,
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 200, description = "The filter now in effect.", body = LogFilterBody),
+        (status = 400, description = "Invalid filter directives.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Only admins can do this, and not with an API key.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Change the log filter",
+    skip(user, request_id, body, pool),
+    fields(username = %user.username, filter = %body.filter)
+)]
+pub async fn put_log_filter(
+    user: Authorized<CanConfigureLogging>,
+    request_id: RequestId,
+    body: web::Json<LogFilterBody>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, LogFilterError> {
+    let filter = EnvFilter::try_new(&body.filter)
+        .map_err(|e| LogFilterError::ValidationError(e.to_string()))?;
+    let log_filter = installed_filter()?;
+    let before = log_filter
+        .current()
+        .context("Failed to read the log filter.")?;
+    let event = AuditEvent::new(&user.username, "logging.filter.update")
+        .request_id(request_id)
+        .before(serde_json::json!({ "filter": before }))
+        .after(serde_json::json!({ "filter": body.filter }));
+    record_audit_event(pool.get_ref(), event)
+        .await
+        .context("Failed to record the log filter change in the audit log.")?;
+    // Logged beforehand, in case the new filter silences it.
+    tracing::warn!(before, after = %body.filter, "Changing the log filter");
+    log_filter
+        .replace(filter)
+        .context("Failed to change the log filter.")?;
+    let filter = log_filter
+        .current()
+        .context("Failed to read the log filter.")?;
+    Ok(HttpResponse::Ok().json(LogFilterBody { filter }))
+}
@@ -0,0 +1,243 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{
+    create_session, delete_session, expired_session_cookie, get_session, session_cookie,
+    two_factor_enabled, verify_second_factor, AuthError, Credentials, LoginThrottle, SecondFactor,
+    TwoFactorError,
+};
+use crate::problem_details::Problem;
+use crate::rate_limit::RateLimiter;
+use crate::request_id::RequestId;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::SecretBox;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoginError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    TooManyAttempts(AuthError),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    SecondFactor(#[from] TwoFactorError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for LoginError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LoginError::InvalidCredentials(_) => StatusCode::UNAUTHORIZED,
+            LoginError::TooManyAttempts(e) => e.status_code(),
+            LoginError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            LoginError::SecondFactor(e) => e.status_code(),
+            LoginError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            LoginError::TooManyAttempts(e) => e.error_response(),
+            LoginError::SecondFactor(e) => e.error_response(),
+            LoginError::UnexpectedError(_) => HttpResponse::build(self.status_code()).finish(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+impl From<AuthError> for LoginError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidCredentials(e) => Self::InvalidCredentials(e),
+            AuthError::TooManyAttempts(_) => Self::TooManyAttempts(e),
+            AuthError::Forbidden(_)
+            | AuthError::MissingScope(_)
+            | AuthError::UnexpectedError(_) => Self::UnexpectedError(anyhow::Error::new(e)),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct LoginData {
+    username: String,
+    #[schema(value_type = String, format = Password)]
+    password: SecretBox<String>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct LoginResponse {
+    second_factor_required: bool,
+}
+
+/// Starts a session. For accounts with two-factor authentication the session
+/// stays pending until a code is submitted to `/admin/login/second-factor`.
+#[utoipa::path(
+    post,
+    path = "/admin/login",
+    tag = "authentication",
+    request_body = LoginData,
+    responses(
+        (status = 200, description = "The session cookie is set.", body = LoginResponse),
+        (status = 400, description = "Invalid request.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "Too many requests: retry after `Retry-After` seconds.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Log in",
+    skip(request, request_id, body, pool, throttle, rate_limiter),
+    fields(username = %body.username)
+)]
+pub async fn login(
+    request: HttpRequest,
+    request_id: RequestId,
+    body: web::Json<LoginData>,
+    pool: web::Data<PgPool>,
+    throttle: web::Data<LoginThrottle>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse, LoginError> {
+    let body = body.into_inner();
+    let credentials = Credentials {
+        username: body.username,
+        password: body.password,
+    };
+    let client_ip = rate_limiter.client_ip(&request);
+    let user = throttle
+        .validate_credentials(credentials, client_ip, &pool)
+        .await?;
+    let second_factor_required = two_factor_enabled(&pool, user.user_id).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token = create_session(&mut *transaction, user.user_id, second_factor_required).await?;
+    if !second_factor_required {
+        record_login(
+            &mut transaction,
+            &user.username,
+            user.user_id,
+            request_id,
+            None,
+        )
+        .await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to log in.")?;
+    Ok(HttpResponse::Ok()
+        .cookie(session_cookie(token))
+        .json(LoginResponse {
+            second_factor_required,
+        }))
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct SecondFactorData {
+    code: Option<String>,
+    recovery_code: Option<String>,
+}
+
+/// Completes the login of a session waiting for its second factor.
+#[utoipa::path(
+    post,
+    path = "/admin/login/second-factor",
+    tag = "authentication",
+    request_body = SecondFactorData,
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "The session cookie is replaced by a fully verified one."),
+        (status = 400, description = "Invalid request.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "Submit a second factor", skip(request, request_id, body, pool))]
+pub async fn login_second_factor(
+    request: HttpRequest,
+    request_id: RequestId,
+    body: web::Json<SecondFactorData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, LoginError> {
+    let (second_factor, method) = match body.into_inner() {
+        SecondFactorData {
+            code: Some(code),
+            recovery_code: None,
+        } => (SecondFactor::Totp(code), "totp"),
+        SecondFactorData {
+            code: None,
+            recovery_code: Some(code),
+        } => (SecondFactor::RecoveryCode(code), "recovery_code"),
+        _ => {
+            return Err(LoginError::ValidationError(
+                "Submit either a `code` or a `recovery_code`.".into(),
+            ))
+        }
+    };
+    let session = get_session(&request, &pool)
+        .await?
+        .filter(|s| s.second_factor_pending)
+        .ok_or_else(|| LoginError::InvalidCredentials(anyhow::anyhow!("No pending login.")))?;
+    let user = session.user;
+    verify_second_factor(&pool, user.user_id, &user.username, second_factor).await?;
+
+    // Hand out a new token so the pending one cannot be reused.
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    delete_session(&mut *transaction, &session.token).await?;
+    let token = create_session(&mut *transaction, user.user_id, false).await?;
+    record_login(
+        &mut transaction,
+        &user.username,
+        user.user_id,
+        request_id,
+        Some(method),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to complete a login.")?;
+    Ok(HttpResponse::Ok().cookie(session_cookie(token)).finish())
+}
+
+/// Ends the current session.
+#[utoipa::path(
+    post,
+    path = "/admin/logout",
+    tag = "authentication",
+    security(("session" = [])),
+    responses((status = 200, description = "The session cookie is cleared."))
+)]
+#[tracing::instrument(name = "Log out", skip(request, pool))]
+pub async fn logout(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, LoginError> {
+    if let Some(session) = get_session(&request, &pool).await? {
+        delete_session(pool.get_ref(), &session.token).await?;
+    }
+    Ok(HttpResponse::Ok().cookie(expired_session_cookie()).finish())
+}
+
+async fn record_login(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    username: &str,
+    user_id: Uuid,
+    request_id: RequestId,
+    second_factor: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let event = AuditEvent::new(username, "user.login")
+        .target(user_id.to_string())
+        .request_id(request_id)
+        .after(serde_json::json!({ "second_factor": second_factor }));
+    record_audit_event(&mut **transaction, event)
+        .await
+        .context("Failed to record the login in the audit log.")
+}
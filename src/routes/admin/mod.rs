@@ -0,0 +1,23 @@
+mod api_keys;
+mod audit;
+mod gdpr;
+mod link_form;
+mod logging;
+mod login;
+mod password;
+mod subscribers_export;
+mod subscribers_import;
+mod two_factor;
+mod users;
+
+pub use api_keys::*;
+pub use audit::*;
+pub use gdpr::*;
+pub use link_form::*;
+pub use logging::*;
+pub use login::*;
+pub use password::*;
+pub use subscribers_export::*;
+pub use subscribers_import::*;
+pub use two_factor::*;
+pub use users::*;
@@ -0,0 +1,339 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{
+    change_password as store_password, generate_token, hash_token, AuthError, AuthenticatedUser,
+    Credentials, LoginThrottle, PasswordPolicy,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::problem_details::Problem;
+use crate::rate_limit::RateLimiter;
+use crate::request_id::RequestId;
+use crate::routes::{FormOrJson, LinkToken};
+use crate::startup::ApplicationBaseUrl;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use secrecy::{ExposeSecret, SecretBox};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// How long a password reset link stays valid.
+const PASSWORD_RESET_TTL: Duration = Duration::hours(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum PasswordError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The password reset link is invalid or has expired.")]
+    UnknownToken,
+    #[error(transparent)]
+    TooManyAttempts(AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for PasswordError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PasswordError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PasswordError::UnknownToken => StatusCode::UNAUTHORIZED,
+            PasswordError::TooManyAttempts(e) => e.status_code(),
+            PasswordError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PasswordError::TooManyAttempts(e) => e.error_response(),
+            PasswordError::UnexpectedError(_) => HttpResponse::build(self.status_code()).finish(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct ChangePasswordData {
+    #[schema(value_type = String, format = Password)]
+    current_password: SecretBox<String>,
+    #[schema(value_type = String, format = Password)]
+    new_password: SecretBox<String>,
+}
+
+/// Changes the password of the authenticated admin, ending all of their
+/// sessions, including the current one.
+#[utoipa::path(
+    post,
+    path = "/admin/password",
+    tag = "authentication",
+    request_body = ChangePasswordData,
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 200, description = "Changed."),
+        (status = 400, description = "The new password does not meet the policy.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "Too many wrong passwords: retry after `Retry-After` seconds.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Change the password of an admin user",
+    skip(user, request_id, body, pool, request, throttle, rate_limiter, password_policy),
+    fields(username = %user.username)
+)]
+// One argument per extractor.
+#[allow(clippy::too_many_arguments)]
+pub async fn change_password(
+    user: AuthenticatedUser,
+    request_id: RequestId,
+    body: web::Json<ChangePasswordData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+    throttle: web::Data<LoginThrottle>,
+    rate_limiter: web::Data<RateLimiter>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, PasswordError> {
+    let body = body.into_inner();
+    if body.new_password.expose_secret() == body.current_password.expose_secret() {
+        return Err(PasswordError::ValidationError(
+            "The new password must be different from the current one.".into(),
+        ));
+    }
+    password_policy
+        .validate(&user.username, &body.new_password)
+        .map_err(PasswordError::ValidationError)?;
+    let credentials = Credentials {
+        username: user.username.clone(),
+        password: body.current_password,
+    };
+    // Throttled like logins, so that a session is no password guessing oracle.
+    let client_ip = rate_limiter.client_ip(&request);
+    if let Err(e) = throttle
+        .validate_credentials(credentials, client_ip, &pool)
+        .await
+    {
+        return match e {
+            AuthError::InvalidCredentials(_) => Err(PasswordError::ValidationError(
+                "The current password is incorrect.".into(),
+            )),
+            AuthError::TooManyAttempts(_) => Err(PasswordError::TooManyAttempts(e)),
+            AuthError::Forbidden(_)
+            | AuthError::MissingScope(_)
+            | AuthError::UnexpectedError(_) => Err(anyhow::Error::new(e).into()),
+        };
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    store_password(&mut transaction, user.user_id, body.new_password).await?;
+    let event = AuditEvent::new(&user.username, "user.password_change")
+        .target(user.user_id.to_string())
+        .request_id(request_id);
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the password change in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change a password.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PasswordResetRequest {
+    username: String,
+}
+
+/// Emails a time-limited reset link if the user exists, has an email address
+/// and has no valid link yet. The response is the same either way, so that
+/// usernames cannot be probed.
+#[utoipa::path(
+    post,
+    path = "/admin/password/reset",
+    tag = "authentication",
+    request_body = PasswordResetRequest,
+    responses(
+        (status = 202, description = "A reset link is on its way, if the user exists."),
+        (status = 429, description = "Too many requests: retry after `Retry-After` seconds.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(request_id, body, pool, email_client, base_url),
+    fields(username = %body.username)
+)]
+pub async fn request_password_reset(
+    request_id: RequestId,
+    body: web::Json<PasswordResetRequest>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, PasswordError> {
+    let user = sqlx::query!(
+        "SELECT user_id, email FROM users WHERE username = $1",
+        body.username
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up the user requesting a password reset.")?;
+    let Some((user_id, Some(email))) = user.map(|u| (u.user_id, u.email)) else {
+        tracing::info!("Password reset requested for a user without an email address");
+        return Ok(HttpResponse::Accepted().finish());
+    };
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "The stored email address of the user is invalid");
+            return Ok(HttpResponse::Accepted().finish());
+        }
+    };
+
+    // One link at a time: requests made while a link is still valid send
+    // nothing, so that they cannot flood the inbox of the user.
+    let token = generate_token();
+    let stored = sqlx::query!(
+        r#"INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        SELECT $1, $2, $3, $4
+        WHERE NOT EXISTS (
+            SELECT 1 FROM password_reset_tokens
+            WHERE user_id = $2 AND used_at IS NULL AND expires_at > $3
+        )"#,
+        hash_token(&token),
+        user_id,
+        Utc::now(),
+        Utc::now() + PASSWORD_RESET_TTL,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the password reset token.")?
+    .rows_affected();
+    if stored == 0 {
+        tracing::info!("Password reset requested while a reset link is still valid");
+        return Ok(HttpResponse::Accepted().finish());
+    }
+
+    let reset_link = format!("{}/admin/password/reset/confirm?token={token}", base_url.0);
+    let email_content = format!(
+        "A password reset was requested for your account.<br />\
+        Click <a href=\"{reset_link}\">here</a> to choose a new password. \
+        The link expires in {} minutes.<br />\
+        If you did not request this, you can ignore this email.",
+        PASSWORD_RESET_TTL.num_minutes()
+    );
+    // Sent off the request, as waiting for the email provider, or failing
+    // along with it, would tell existing usernames apart.
+    let email_client = email_client.into_inner();
+    let send = async move {
+        if let Err(e) = email_client
+            .create_email(&email, "Reset your password", &email_content)
+            .await
+        {
+            tracing::error!(error.cause_chain = ?e, "Failed to send the password reset email");
+        }
+    };
+    tokio::spawn(request_id.scope(send).instrument(tracing::Span::current()));
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// The page the reset link opens.
+#[tracing::instrument(name = "Render the password reset form", skip(query))]
+pub async fn reset_password_form(query: web::Query<LinkToken>) -> HttpResponse {
+    query.form_page(
+        "Choose a new password",
+        "/admin/password/reset/confirm",
+        "<label>New password \
+        <input type=\"password\" name=\"new_password\" autocomplete=\"new-password\" required>\
+        </label>",
+    )
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PasswordResetData {
+    token: String,
+    #[schema(value_type = String, format = Password)]
+    new_password: SecretBox<String>,
+}
+
+/// Sets a new password with the token of a reset link.
+#[utoipa::path(
+    post,
+    path = "/admin/password/reset/confirm",
+    tag = "authentication",
+    request_body(
+        content(
+            (PasswordResetData = "application/x-www-form-urlencoded"),
+            (PasswordResetData = "application/json"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "Changed."),
+        (status = 400, description = "The new password does not meet the policy.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The token is unknown, used or expired.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Reset a password",
+    skip(request_id, body, pool, password_policy)
+)]
+pub async fn reset_password(
+    request_id: RequestId,
+    body: FormOrJson<PasswordResetData>,
+    pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, PasswordError> {
+    let body = body.0;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let (user_id, username) = consume_password_reset_token(&mut transaction, &body.token)
+        .await
+        .context("Failed to validate the password reset token.")?
+        .ok_or(PasswordError::UnknownToken)?;
+    password_policy
+        .validate(&username, &body.new_password)
+        .map_err(PasswordError::ValidationError)?;
+    store_password(&mut transaction, user_id, body.new_password).await?;
+    let event = AuditEvent::new(&username, "user.password_reset")
+        .target(user_id.to_string())
+        .request_id(request_id);
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the password reset in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset a password.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Marks the token as used and returns its user if it is valid.
+/// Every other pending reset token of that user is invalidated as well.
+#[tracing::instrument(name = "Consume a password reset token", skip_all)]
+async fn consume_password_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT t.user_id, u.username FROM password_reset_tokens t
+        JOIN users u ON u.user_id = t.user_id
+        WHERE t.token_hash = $1 AND t.used_at IS NULL AND t.expires_at > now()
+        FOR UPDATE"#,
+        hash_token(token),
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    transaction
+        .execute(sqlx::query!(
+            "UPDATE password_reset_tokens SET used_at = $1 WHERE user_id = $2 AND used_at IS NULL",
+            Utc::now(),
+            row.user_id
+        ))
+        .await?;
+    Ok(Some((row.user_id, row.username)))
+}
@@ -0,0 +1,311 @@
+use crate::authentication::{Authorized, CanReadSubscribers};
+use crate::problem_details::Problem;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, TryStreamExt};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ExportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ExportError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ExportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+        }
+    }
+}
+
+/// Query-string filters selecting the subscribers to export.
+#[derive(serde::Deserialize, Debug, Default, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriberFilters {
+    pub status: Option<SubscriptionStatus>,
+    /// Case-insensitive substring of the subscriber email.
+    pub email: Option<String>,
+    pub subscribed_after: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+}
+
+impl SubscriberFilters {
+    /// Appends a `WHERE` clause for the filters that are set.
+    pub fn push_where(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        query.push(" WHERE TRUE");
+        if let Some(status) = self.status {
+            query.push(" AND status = ").push_bind(status.as_str());
+        }
+        if let Some(email) = &self.email {
+            query
+                .push(" AND email ILIKE '%' || ")
+                .push_bind(escape_like(email))
+                .push(" || '%' ESCAPE '\\'");
+        }
+        if let Some(subscribed_after) = self.subscribed_after {
+            query
+                .push(" AND subscribed_at >= ")
+                .push_bind(subscribed_after);
+        }
+        if let Some(subscribed_before) = self.subscribed_before {
+            query
+                .push(" AND subscribed_at < ")
+                .push_bind(subscribed_before);
+        }
+    }
+}
+
+/// Matches `%`, `_` and `\` literally in a `LIKE` pattern.
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/jsonl; charset=utf-8",
+        }
+    }
+
+    fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportColumn {
+    Id,
+    Email,
+    Name,
+    SubscribedAt,
+    Status,
+}
+
+impl ExportColumn {
+    const ALL: [ExportColumn; 5] = [
+        ExportColumn::Id,
+        ExportColumn::Email,
+        ExportColumn::Name,
+        ExportColumn::SubscribedAt,
+        ExportColumn::Status,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ExportColumn::Id => "id",
+            ExportColumn::Email => "email",
+            ExportColumn::Name => "name",
+            ExportColumn::SubscribedAt => "subscribed_at",
+            ExportColumn::Status => "status",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| format!("`{s}` is not an exportable column."))
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportParameters {
+    #[serde(default)]
+    format: ExportFormat,
+    /// Comma-separated list of columns. Every column is exported when omitted.
+    columns: Option<String>,
+}
+
+impl ExportParameters {
+    fn columns(&self) -> Result<Vec<ExportColumn>, String> {
+        match &self.columns {
+            None => Ok(ExportColumn::ALL.to_vec()),
+            Some(columns) => columns
+                .split(',')
+                .map(|c| ExportColumn::parse(c.trim()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    status: String,
+}
+
+impl SubscriberRow {
+    fn field(&self, column: ExportColumn) -> String {
+        match column {
+            ExportColumn::Id => self.id.to_string(),
+            ExportColumn::Email => self.email.clone(),
+            ExportColumn::Name => self.name.clone(),
+            ExportColumn::SubscribedAt => self.subscribed_at.to_rfc3339(),
+            ExportColumn::Status => self.status.clone(),
+        }
+    }
+}
+
+/// Streams the subscribers matching the filters as a file download.
+#[utoipa::path(
+    get,
+    path = "/admin/subscribers/export",
+    tag = "subscribers",
+    params(ExportParameters, SubscriberFilters),
+    security(("basic" = []), ("session" = []), ("api_key" = [])),
+    responses(
+        (status = 200, description = "OK.", content(
+            (String = "text/csv"),
+            (String = "application/jsonl"),
+        )),
+        (status = 400, description = "An unknown column was requested.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The role or the scopes of the API key do not allow it.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Export subscribers",
+    skip(user, parameters, filters, pool),
+    fields(username = %user.username, format = ?parameters.format)
+)]
+pub async fn export_subscribers(
+    user: Authorized<CanReadSubscribers>,
+    parameters: web::Query<ExportParameters>,
+    filters: web::Query<SubscriberFilters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ExportError> {
+    let columns = parameters.columns().map_err(ExportError::ValidationError)?;
+    let format = parameters.format;
+    let rows = stream_subscribers(pool.get_ref().clone(), filters.into_inner());
+    let body = encode_rows(rows, format, columns).map_err(|e| {
+        tracing::error!(error.cause_chain = ?e, "Failed to stream the subscriber export");
+        ExportError::UnexpectedError(e)
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscribers.{}",
+                format.file_extension()
+            ))],
+        })
+        .streaming(body))
+}
+
+/// Streams matching rows as Postgres returns them, without buffering the result set.
+fn stream_subscribers(
+    pool: PgPool,
+    filters: SubscriberFilters,
+) -> impl Stream<Item = Result<SubscriberRow, anyhow::Error>> {
+    async_stream::try_stream! {
+        let mut query =
+            QueryBuilder::new("SELECT id, email, name, subscribed_at, status FROM subscriptions");
+        filters.push_where(&mut query);
+        query.push(" ORDER BY subscribed_at, id");
+        let mut rows = query.build_query_as::<SubscriberRow>().fetch(&pool);
+        while let Some(row) = rows
+            .try_next()
+            .await
+            .context("Failed to fetch subscribers for the export.")?
+        {
+            yield row;
+        }
+    }
+}
+
+fn encode_rows(
+    rows: impl Stream<Item = Result<SubscriberRow, anyhow::Error>>,
+    format: ExportFormat,
+    columns: Vec<ExportColumn>,
+) -> impl Stream<Item = Result<web::Bytes, anyhow::Error>> {
+    async_stream::try_stream! {
+        if format == ExportFormat::Csv {
+            yield encode_csv_record(columns.iter().map(|c| c.name().to_string()))?;
+        }
+        futures_util::pin_mut!(rows);
+        while let Some(row) = rows.try_next().await? {
+            match format {
+                ExportFormat::Csv => {
+                    yield encode_csv_record(
+                        columns.iter().map(|c| neutralize_formula(row.field(*c))),
+                    )?;
+                }
+                ExportFormat::Jsonl => {
+                    let object: serde_json::Map<String, serde_json::Value> = columns
+                        .iter()
+                        .map(|c| (c.name().to_string(), row.field(*c).into()))
+                        .collect();
+                    let mut line = serde_json::to_vec(&object)?;
+                    line.push(b'\n');
+                    yield web::Bytes::from(line);
+                }
+            }
+        }
+    }
+}
+
+/// Spreadsheets run cells starting with these characters as formulas: prefixing
+/// them with `'` makes them text, so that a subscriber cannot plant a formula
+/// in the export an admin opens.
+fn neutralize_formula(cell: String) -> String {
+    if cell.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{cell}")
+    } else {
+        cell
+    }
+}
+
+fn encode_csv_record(record: impl Iterator<Item = String>) -> Result<web::Bytes, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(record)?;
+    Ok(web::Bytes::from(writer.into_inner()?))
+}
@@ -0,0 +1,539 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{Authorized, CanEditSubscribers};
+use crate::domain::{EmailCanonicalizer, NewSubscriber};
+use crate::email_client::EmailClient;
+use crate::email_validation::EmailDomainPolicy;
+use crate::problem_details::Problem;
+use crate::request_id::RequestId;
+use crate::routes::{
+    create_and_send_confirmation_email, generate_subscription_token, ErasureKey, FormData,
+};
+use crate::startup::ApplicationBaseUrl;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use csv_async::{AsyncReaderBuilder, ErrorKind, Trim};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::io::AsyncRead;
+use tokio_util::io::StreamReader;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Number of rows written to the database in a single statement.
+const IMPORT_BATCH_SIZE: usize = 1000;
+/// Number of domain checks, or of confirmation emails, in flight at once.
+const IMPORT_CONCURRENCY: usize = 16;
+
+#[derive(
+    serde::Deserialize,
+    serde::Serialize,
+    clap::ValueEnum,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Store the subscribers as confirmed without contacting them.
+    Confirmed,
+    /// Store the subscribers as pending and send each of them a confirmation
+    /// email once they are all stored.
+    #[default]
+    SendConfirmation,
+}
+
+impl ImportMode {
+    fn status(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::SendConfirmation => "pending_confirmation",
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Default, utoipa::ToSchema)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub failed: u64,
+    pub errors: Vec<ImportRowError>,
+}
+
+/// A rejected CSV row. `row` is the 1-based index of the record, header excluded.
+#[derive(serde::Serialize, Debug, utoipa::ToSchema)]
+pub struct ImportRowError {
+    pub row: u64,
+    pub error: String,
+}
+
+impl ImportReport {
+    fn reject(&mut self, row: u64, error: String) {
+        self.failed += 1;
+        self.errors.push(ImportRowError { row, error });
+    }
+
+    /// Summary of the import for the audit log. Rejected rows are left out
+    /// because their error messages may contain addresses.
+    pub fn audit_event(&self, actor: impl Into<String>, mode: ImportMode) -> AuditEvent {
+        AuditEvent::new(actor, "subscribers.import").after(serde_json::json!({
+            "mode": mode,
+            "imported": self.imported,
+            "failed": self.failed,
+        }))
+    }
+}
+
+/// The confirmation emails owed to the subscribers an import stored as pending,
+/// with the rows they were imported from.
+#[derive(Default)]
+pub struct PendingConfirmations(Vec<(u64, NewSubscriber, String)>);
+
+/// How sending the confirmation emails of an import went.
+#[derive(Debug, Default)]
+pub struct ConfirmationReport {
+    pub sent: u64,
+    /// The rows whose subscriber was not sent a confirmation.
+    pub unsent_rows: Vec<u64>,
+}
+
+impl ConfirmationReport {
+    /// Recorded once the emails are sent, as the import itself was answered
+    /// before they were.
+    pub fn audit_event(&self, actor: impl Into<String>) -> AuditEvent {
+        AuditEvent::new(actor, "subscribers.import.confirmations").after(serde_json::json!({
+            "sent": self.sent,
+            "unsent_rows": self.unsent_rows,
+        }))
+    }
+}
+
+impl PendingConfirmations {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Sends the emails a few at a time. The subscribers of the rows that were
+    /// not sent one stay pending: subscribing again sends them a new confirmation.
+    #[tracing::instrument(
+        name = "Send the confirmation emails of an import",
+        skip_all,
+        fields(count = self.0.len())
+    )]
+    pub async fn send(self, email_client: &EmailClient, base_url: &str) -> ConfirmationReport {
+        let mut report = stream::iter(self.0)
+            .map(|(row, new_subscriber, subscription_token)| async move {
+                let result = create_and_send_confirmation_email(
+                    email_client,
+                    new_subscriber,
+                    base_url,
+                    &subscription_token,
+                )
+                .await;
+                (row, result)
+            })
+            .buffer_unordered(IMPORT_CONCURRENCY)
+            .fold(
+                ConfirmationReport::default(),
+                |mut report, (row, result)| async move {
+                    match result {
+                        Ok(()) => report.sent += 1,
+                        Err(error) => {
+                            tracing::warn!(
+                                row,
+                                error.cause_chain = ?error,
+                                "Failed to send a confirmation email to an imported subscriber"
+                            );
+                            report.unsent_rows.push(row);
+                        }
+                    }
+                    report
+                },
+            )
+            .await;
+        if !report.unsent_rows.is_empty() {
+            tracing::error!(
+                failed = report.unsent_rows.len(),
+                "Some confirmation emails of an import were not sent"
+            );
+        }
+        report.unsent_rows.sort_unstable();
+        report
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ImportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ImportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParameters {
+    #[serde(default)]
+    mode: ImportMode,
+}
+
+/// Imports `email,name` records from a CSV file, reporting the rejected rows.
+/// Confirmation emails are sent in the background once the rows are stored:
+/// the rows whose email could not be sent are recorded in the audit log.
+#[utoipa::path(
+    post,
+    path = "/admin/subscribers/import",
+    tag = "subscribers",
+    params(ImportParameters),
+    request_body(content = String, content_type = "text/csv"),
+    security(("basic" = []), ("session" = []), ("api_key" = [])),
+    responses(
+        (status = 200, description = "OK.", body = ImportReport),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The role or the scopes of the API key do not allow it.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Import subscribers from an uploaded CSV file",
+    skip(user, request_id, parameters, payload, pool, email_client, base_url, erasure_key, email_policy, canonicalizer),
+    fields(username = %user.username, mode = ?parameters.mode)
+)]
+// One argument per extractor.
+#[allow(clippy::too_many_arguments)]
+pub async fn import_subscribers_csv(
+    user: Authorized<CanEditSubscribers>,
+    request_id: RequestId,
+    parameters: web::Query<ImportParameters>,
+    payload: web::Payload,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    erasure_key: web::Data<ErasureKey>,
+    email_policy: web::Data<EmailDomainPolicy>,
+    canonicalizer: web::Data<EmailCanonicalizer>,
+) -> Result<HttpResponse, ImportError> {
+    // The request payload is not `Send`, so it is piped into the CSV reader
+    // through an in-memory duplex instead of being handed over directly.
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    let upload = async move {
+        let mut payload = StreamReader::new(payload.map_err(std::io::Error::other));
+        tokio::io::copy(&mut payload, &mut writer)
+            .await
+            .context("Failed to read the CSV upload.")
+    };
+    let import = import_subscribers(
+        reader,
+        parameters.mode,
+        &pool,
+        &erasure_key,
+        &email_policy,
+        &canonicalizer,
+    );
+    let (_, (report, confirmations)) = futures_util::try_join!(upload, import)?;
+    let event = report
+        .audit_event(&user.username, parameters.mode)
+        .request_id(request_id);
+    record_audit_event(pool.get_ref(), event)
+        .await
+        .context("Failed to record the import in the audit log.")?;
+    if !confirmations.is_empty() {
+        // Large imports owe too many emails to send them before answering.
+        let send = send_confirmations(
+            confirmations,
+            email_client.into_inner(),
+            base_url.into_inner(),
+            pool.into_inner(),
+            user.username.clone(),
+        );
+        tokio::spawn(request_id.scope(send).instrument(tracing::Span::current()));
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
+async fn send_confirmations(
+    confirmations: PendingConfirmations,
+    email_client: Arc<EmailClient>,
+    base_url: Arc<ApplicationBaseUrl>,
+    pool: Arc<PgPool>,
+    actor: String,
+) {
+    let report = confirmations.send(&email_client, &base_url.0).await;
+    let mut event = report.audit_event(actor);
+    if let Some(request_id) = RequestId::current() {
+        event = event.request_id(request_id);
+    }
+    if let Err(e) = record_audit_event(pool.as_ref(), event).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            "Failed to record the confirmations of an import in the audit log"
+        );
+    }
+}
+
+/// Reads `email,name` records from `reader` and stores them in batches,
+/// returning the confirmation emails to send when storing them as pending.
+///
+/// Invalid, duplicated or policy-rejected rows are collected in the returned
+/// report instead of aborting the import; only I/O and database failures are
+/// returned as errors.
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(reader, pool, erasure_key, email_policy, canonicalizer)
+)]
+pub async fn import_subscribers<R>(
+    reader: R,
+    mode: ImportMode,
+    pool: &PgPool,
+    erasure_key: &ErasureKey,
+    email_policy: &EmailDomainPolicy,
+    canonicalizer: &EmailCanonicalizer,
+) -> Result<(ImportReport, PendingConfirmations), anyhow::Error>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut report = ImportReport::default();
+    let mut confirmations = PendingConfirmations::default();
+    let mut deserializer = AsyncReaderBuilder::new()
+        .trim(Trim::All)
+        .create_deserializer(reader);
+    let mut records = deserializer.deserialize::<FormData>();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut row = 0;
+    while let Some(record) = records.next().await {
+        row += 1;
+        match record {
+            Ok(form) => match form.new_subscriber(canonicalizer) {
+                Ok(new_subscriber) => batch.push((row, new_subscriber)),
+                Err(error) => report.reject(row, error.to_string()),
+            },
+            Err(error) if matches!(error.kind(), ErrorKind::Io(_)) => {
+                return Err(anyhow::Error::new(error).context("Failed to read the CSV upload."));
+            }
+            Err(error) => report.reject(row, error.to_string()),
+        }
+        if batch.len() == IMPORT_BATCH_SIZE {
+            let batch = std::mem::replace(&mut batch, Vec::with_capacity(IMPORT_BATCH_SIZE));
+            import_batch(
+                batch,
+                mode,
+                pool,
+                erasure_key,
+                email_policy,
+                &mut report,
+                &mut confirmations,
+            )
+            .await?;
+        }
+    }
+    if !batch.is_empty() {
+        import_batch(
+            batch,
+            mode,
+            pool,
+            erasure_key,
+            email_policy,
+            &mut report,
+            &mut confirmations,
+        )
+        .await?;
+    }
+    Ok((report, confirmations))
+}
+
+#[tracing::instrument(
+    name = "Import a batch of subscribers",
+    skip(batch, pool, erasure_key, email_policy, report, confirmations),
+    fields(batch_size = batch.len())
+)]
+async fn import_batch(
+    batch: Vec<(u64, NewSubscriber)>,
+    mode: ImportMode,
+    pool: &PgPool,
+    erasure_key: &ErasureKey,
+    email_policy: &EmailDomainPolicy,
+    report: &mut ImportReport,
+    confirmations: &mut PendingConfirmations,
+) -> Result<(), anyhow::Error> {
+    // The same domain checks as signups, run before any connection is held.
+    let checks: Vec<_> = stream::iter(batch)
+        .map(|(row, new_subscriber)| async move {
+            let result = email_policy.check(&new_subscriber.email).await;
+            (row, new_subscriber, result)
+        })
+        .buffered(IMPORT_CONCURRENCY)
+        .collect()
+        .await;
+    let mut batch = Vec::with_capacity(checks.len());
+    for (row, new_subscriber, result) in checks {
+        match result {
+            Ok(()) => batch.push((row, new_subscriber)),
+            Err(error) => report.reject(row, error.to_string()),
+        }
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let erased = get_erased_email_hashes(&mut transaction, &batch, erasure_key)
+        .await
+        .context("Failed to look up erased subscribers.")?;
+    let batch: Vec<_> = batch
+        .into_iter()
+        .filter(|(row, new_subscriber)| {
+            let is_erased = erased.contains(&erasure_key.hash(new_subscriber.email.as_ref()));
+            if is_erased {
+                report.reject(
+                    *row,
+                    format!(
+                        "{} was erased and cannot be imported.",
+                        new_subscriber.email
+                    ),
+                );
+            }
+            !is_erased
+        })
+        .collect();
+    let mut inserted = insert_subscribers(&mut transaction, &batch, mode)
+        .await
+        .context("Failed to insert a batch of subscribers in the database.")?;
+
+    let mut accepted = Vec::with_capacity(inserted.len());
+    for (row, new_subscriber) in batch {
+        match inserted.remove(new_subscriber.email.canonical()) {
+            Some(subscriber_id) => accepted.push((row, subscriber_id, new_subscriber)),
+            None => report.reject(
+                row,
+                format!("{} is already subscribed.", new_subscriber.email),
+            ),
+        }
+    }
+
+    if mode == ImportMode::Confirmed {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store imported subscribers.")?;
+        report.imported += accepted.len() as u64;
+        return Ok(());
+    }
+
+    let subscription_tokens: Vec<String> = accepted
+        .iter()
+        .map(|_| generate_subscription_token())
+        .collect();
+    let subscriber_ids: Vec<Uuid> = accepted.iter().map(|(_, id, _)| *id).collect();
+    store_tokens(&mut transaction, &subscriber_ids, &subscription_tokens)
+        .await
+        .context("Failed to store the confirmation tokens for imported subscribers.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store imported subscribers.")?;
+    report.imported += accepted.len() as u64;
+    confirmations.0.extend(
+        accepted
+            .into_iter()
+            .zip(subscription_tokens)
+            .map(|((row, _, new_subscriber), token)| (row, new_subscriber, token)),
+    );
+    Ok(())
+}
+
+/// Returns the hashes of the batch addresses that have an erasure tombstone.
+#[tracing::instrument(name = "Get erased subscribers", skip(transaction, batch, erasure_key))]
+async fn get_erased_email_hashes(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch: &[(u64, NewSubscriber)],
+    erasure_key: &ErasureKey,
+) -> Result<HashSet<String>, sqlx::Error> {
+    let email_hashes: Vec<String> = batch
+        .iter()
+        .map(|(_, s)| erasure_key.hash(s.email.as_ref()))
+        .collect();
+    let rows = sqlx::query!(
+        "SELECT email_hash FROM erased_subscribers WHERE email_hash = ANY($1)",
+        &email_hashes,
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.email_hash).collect())
+}
+
+/// Inserts the batch with a single multi-row statement, skipping addresses that
+/// are already stored. Returns the ids of the inserted rows keyed by canonical email.
+#[tracing::instrument(
+    name = "Saving a batch of imported subscribers in the database",
+    skip(transaction, batch)
+)]
+async fn insert_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch: &[(u64, NewSubscriber)],
+    mode: ImportMode,
+) -> Result<HashMap<String, Uuid>, sqlx::Error> {
+    let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = batch
+        .iter()
+        .map(|(_, s)| s.email.as_ref().to_owned())
+        .collect();
+    let canonical_emails: Vec<String> = batch
+        .iter()
+        .map(|(_, s)| s.email.canonical().to_owned())
+        .collect();
+    let names: Vec<String> = batch
+        .iter()
+        .map(|(_, s)| s.name.as_ref().to_owned())
+        .collect();
+    let rows = sqlx::query!(
+        r#"insert into subscriptions(id, email, email_canonical, name, subscribed_at, status)
+        select id, email, email_canonical, name, $5::timestamptz, $6::text
+        from unnest($1::uuid[], $2::text[], $3::text[], $4::text[])
+            as batch(id, email, email_canonical, name)
+        on conflict (email_canonical) do nothing
+        returning id, email_canonical
+        "#,
+        &ids,
+        &emails,
+        &canonical_emails,
+        &names,
+        Utc::now(),
+        mode.status(),
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.email_canonical, r.id))
+        .collect())
+}
+
+#[tracing::instrument(
+    name = "Store a batch of subscription tokens in the database",
+    skip(transaction, subscriber_ids, subscription_tokens)
+)]
+async fn store_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+    subscription_tokens: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        SELECT * FROM unnest($1::text[], $2::uuid[])"#,
+        subscription_tokens,
+        subscriber_ids,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::TotpEnrollment;
+use crate::authentication::{
+    confirm_totp_enrollment, start_totp_enrollment, AuthenticatedUser, TwoFactorError,
+};
+use crate::problem_details::Problem;
+use crate::request_id::RequestId;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// Starts enrolling an authenticator app. It is only used once confirmed.
+#[utoipa::path(
+    post,
+    path = "/admin/2fa/enroll",
+    tag = "authentication",
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 200, description = "OK.", body = TotpEnrollment),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Start two-factor enrolment",
+    skip(user, pool),
+    fields(username = %user.username)
+)]
+pub async fn enroll_totp(
+    user: AuthenticatedUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, TwoFactorError> {
+    let enrollment = start_totp_enrollment(&pool, user.user_id, &user.username).await?;
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct ConfirmTotpData {
+    code: String,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+/// Confirms the enrolment with a first code and hands out the recovery codes.
+#[utoipa::path(
+    post,
+    path = "/admin/2fa/confirm",
+    tag = "authentication",
+    request_body = ConfirmTotpData,
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 200, description = "OK.", body = RecoveryCodes),
+        (status = 400, description = "Invalid request.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Confirm two-factor enrolment",
+    skip(user, request_id, body, pool),
+    fields(username = %user.username)
+)]
+pub async fn confirm_totp(
+    user: AuthenticatedUser,
+    request_id: RequestId,
+    body: web::Json<ConfirmTotpData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, TwoFactorError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let recovery_codes =
+        confirm_totp_enrollment(&mut transaction, user.user_id, &user.username, &body.code).await?;
+    let event = AuditEvent::new(&user.username, "user.two_factor_enable")
+        .target(user.user_id.to_string())
+        .request_id(request_id);
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the two-factor enrolment in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")?;
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
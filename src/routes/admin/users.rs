@@ -0,0 +1,261 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{
+    create_user, generate_token, hash_token, Authorized, CanManageUsers, CreateUserError,
+    PasswordPolicy, Role,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::problem_details::{escape_html, Problem};
+use crate::request_id::RequestId;
+use crate::routes::{FormOrJson, LinkToken};
+use crate::startup::ApplicationBaseUrl;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use secrecy::SecretBox;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// How long an invitation link stays valid.
+const INVITATION_TTL: Duration = Duration::days(7);
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserManagementError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The invitation is invalid, has expired or was already accepted.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for UserManagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UserManagementError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            UserManagementError::UnknownToken => StatusCode::UNAUTHORIZED,
+            UserManagementError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<CreateUserError> for UserManagementError {
+    fn from(e: CreateUserError) -> Self {
+        match e {
+            CreateUserError::AlreadyExists => Self::ValidationError(e.to_string()),
+            CreateUserError::UnexpectedError(e) => Self::UnexpectedError(e),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct InvitationData {
+    email: String,
+    role: String,
+}
+
+/// Emails an invitation to join as an admin with the given role.
+#[utoipa::path(
+    post,
+    path = "/admin/users/invitations",
+    tag = "users",
+    request_body = InvitationData,
+    security(("basic" = []), ("session" = [])),
+    responses(
+        (status = 200, description = "The invitation is on its way."),
+        (status = 400, description = "Invalid request.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Only admins can do this, and not with an API key.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Invite an admin user",
+    skip(user, request_id, body, pool, email_client, base_url),
+    fields(username = %user.username, role = %body.role)
+)]
+pub async fn invite_user(
+    user: Authorized<CanManageUsers>,
+    request_id: RequestId,
+    body: web::Json<InvitationData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, UserManagementError> {
+    let body = body.into_inner();
+    let email = SubscriberEmail::parse(body.email)
+        .map_err(|e| UserManagementError::ValidationError(e.to_string()))?;
+    let role = Role::try_from(body.role).map_err(UserManagementError::ValidationError)?;
+
+    let token = generate_token();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    transaction
+        .execute(sqlx::query!(
+            r#"INSERT INTO user_invitations
+            (token_hash, email, role, invited_by, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
+            hash_token(&token),
+            email.as_ref(),
+            role.as_str(),
+            user.user_id,
+            Utc::now(),
+            Utc::now() + INVITATION_TTL,
+        ))
+        .await
+        .context("Failed to store the invitation.")?;
+    let event = AuditEvent::new(&user.username, "user.invite")
+        .target(email.as_ref())
+        .request_id(request_id)
+        .after(serde_json::json!({ "role": role.as_str() }));
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the invitation in the audit log.")?;
+
+    let invitation_link = format!("{}/admin/invitations/accept?token={token}", base_url.0);
+    let email_content = format!(
+        "{} invited you to manage our newsletter as {}.<br />\
+        Click <a href=\"{invitation_link}\">here</a> to choose a username and password. \
+        The invitation expires in {} days.",
+        escape_html(&user.username),
+        escape_html(role.as_str()),
+        INVITATION_TTL.num_days()
+    );
+    // Sent before committing, so that an invitation which was not delivered
+    // is not stored either and can simply be sent again.
+    email_client
+        .create_email(&email, "You have been invited", &email_content)
+        .await
+        .context("Failed to send the invitation email.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an invitation.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct AcceptInvitationData {
+    token: String,
+    username: String,
+    #[schema(value_type = String, format = Password)]
+    password: SecretBox<String>,
+}
+
+struct PendingInvitation {
+    email: String,
+    role: Role,
+    invited_by: Uuid,
+}
+
+/// Creates the invited user.
+#[utoipa::path(
+    post,
+    path = "/admin/invitations/accept",
+    tag = "users",
+    request_body(
+        content(
+            (AcceptInvitationData = "application/x-www-form-urlencoded"),
+            (AcceptInvitationData = "application/json"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "The user can now log in."),
+        (status = 400, description = "Invalid request.", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The token is unknown, used or expired.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(request_id, body, pool, password_policy),
+    fields(username = %body.0.username)
+)]
+pub async fn accept_invitation(
+    request_id: RequestId,
+    body: FormOrJson<AcceptInvitationData>,
+    pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, UserManagementError> {
+    let body = body.0;
+    let username = body.username.trim();
+    if username.is_empty() {
+        return Err(UserManagementError::ValidationError(
+            "The username must not be empty.".into(),
+        ));
+    }
+    password_policy
+        .validate(username, &body.password)
+        .map_err(UserManagementError::ValidationError)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let invitation = consume_invitation(&mut transaction, &body.token)
+        .await
+        .context("Failed to validate the invitation token.")?
+        .ok_or(UserManagementError::UnknownToken)?;
+    let user_id = create_user(
+        &mut *transaction,
+        username,
+        body.password,
+        invitation.role,
+        Some(&invitation.email),
+    )
+    .await?;
+    let event = AuditEvent::new(username, "user.create")
+        .target(user_id.to_string())
+        .request_id(request_id)
+        .after(serde_json::json!({
+            "role": invitation.role.as_str(),
+            "invited_by": invitation.invited_by,
+        }));
+    record_audit_event(&mut *transaction, event)
+        .await
+        .context("Failed to record the new user in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept an invitation.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The page the invitation link opens.
+#[tracing::instrument(name = "Render the invitation form", skip(query))]
+pub async fn accept_invitation_form(query: web::Query<LinkToken>) -> HttpResponse {
+    query.form_page(
+        "Accept the invitation",
+        "/admin/invitations/accept",
+        "<label>Username \
+        <input name=\"username\" autocomplete=\"username\" required></label>\
+        <label>Password \
+        <input type=\"password\" name=\"password\" autocomplete=\"new-password\" required>\
+        </label>",
+    )
+}
+
+#[tracing::instrument(name = "Consume an invitation token", skip_all)]
+async fn consume_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<PendingInvitation>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"UPDATE user_invitations SET accepted_at = $1
+        WHERE token_hash = $2 AND accepted_at IS NULL AND expires_at > $1
+        RETURNING email, role, invited_by"#,
+        Utc::now(),
+        hash_token(token),
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    row.map(|r| {
+        Ok(PendingInvitation {
+            email: r.email,
+            role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+            invited_by: r.invited_by,
+        })
+    })
+    .transpose()
+}
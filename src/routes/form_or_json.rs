@@ -0,0 +1,29 @@
+use actix_web::dev::Payload;
+use actix_web::{error, web, FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::{ready, LocalBoxFuture};
+use futures_util::{FutureExt, TryFutureExt};
+use serde::de::DeserializeOwned;
+
+/// A body posted by one of our HTML forms, or the same fields as a JSON
+/// object, posted by our apps and API clients.
+pub struct FormOrJson<T>(pub T);
+
+impl<T: DeserializeOwned + 'static> FromRequest for FormOrJson<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        match req.content_type() {
+            "application/x-www-form-urlencoded" => web::Form::<T>::from_request(req, payload)
+                .map_ok(|form| Self(form.into_inner()))
+                .boxed_local(),
+            "application/json" => web::Json::<T>::from_request(req, payload)
+                .map_ok(|json| Self(json.into_inner()))
+                .boxed_local(),
+            _ => ready(Err(error::ErrorUnsupportedMediaType(
+                "The body must be 'application/x-www-form-urlencoded' or 'application/json'.",
+            )))
+            .boxed_local(),
+        }
+    }
+}
@@ -1,5 +1,143 @@
-use actix_web::HttpResponse;
+use crate::configuration::HealthSettings;
+use crate::email_client::EmailClient;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::time::{Duration, Instant};
 
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Liveness: the process is up and serving requests. It checks nothing else,
+/// so that an unreachable dependency does not get the instance restarted.
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ComponentStatus {
+    Up,
+    Down,
+}
+
+#[derive(serde::Serialize)]
+struct ComponentHealth {
+    status: ComponentStatus,
+    /// Instances are only ready when every required component is up.
+    required: bool,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Readiness {
+    components: BTreeMap<&'static str, ComponentHealth>,
+}
+
+/// Runs `check` within the configured timeout and reports how it went.
+/// Probers only get the outermost error message: the cause chain is logged.
+async fn probe<F>(
+    name: &'static str,
+    required: bool,
+    timeout: Duration,
+    check: F,
+) -> (&'static str, ComponentHealth)
+where
+    F: Future<Output = Result<(), anyhow::Error>>,
+{
+    let started_at = Instant::now();
+    let result = match tokio::time::timeout(timeout, check).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("Timed out after {timeout:?}.")),
+    };
+    let latency_ms = started_at.elapsed().as_millis();
+    let (status, error) = match result {
+        Ok(()) => (ComponentStatus::Up, None),
+        Err(e) => {
+            tracing::warn!(component = name, error.cause_chain = ?e, "Readiness check failed");
+            (ComponentStatus::Down, Some(e.to_string()))
+        }
+    };
+    let health = ComponentHealth {
+        status,
+        required,
+        latency_ms,
+        error,
+    };
+    (name, health)
+}
+
+async fn ping_database(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!("SELECT 1 AS ping")
+        .fetch_one(pool)
+        .await
+        .context("Failed to reach the database.")?;
+    Ok(())
+}
+
+/// Fails when the database schema is behind the migrations this build expects.
+async fn check_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    // Not checked at compile time: the table belongs to sqlx, not to our schema.
+    let applied: HashSet<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+            .context("Failed to read the applied migrations.")?
+            .into_iter()
+            .collect();
+    let pending = MIGRATOR
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .count();
+    if pending > 0 {
+        anyhow::bail!("{pending} migration(s) have not been applied.");
+    }
+    Ok(())
+}
+
+/// Readiness: whether this instance can serve traffic, with a breakdown per
+/// dependency. Answers with a 503 when a required dependency is down.
+pub async fn readiness_check(
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    settings: web::Data<HealthSettings>,
+) -> HttpResponse {
+    let timeout = settings.timeout();
+    let email_provider = async {
+        if settings.probe_email_provider {
+            let check = async {
+                email_client
+                    .probe()
+                    .await
+                    .context("Failed to reach the email provider.")
+            };
+            Some(probe("email_provider", false, timeout, check).await)
+        } else {
+            None
+        }
+    };
+    let (database, migrations, email_provider) = tokio::join!(
+        probe("database", true, timeout, ping_database(&pool)),
+        probe("migrations", true, timeout, check_migrations(&pool)),
+        email_provider,
+    );
+    let readiness = Readiness {
+        components: [database, migrations]
+            .into_iter()
+            .chain(email_provider)
+            .collect(),
+    };
+    let ready = readiness
+        .components
+        .values()
+        .all(|c| !c.required || matches!(c.status, ComponentStatus::Up));
+    if ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
@@ -1,8 +1,12 @@
+mod admin;
+mod form_or_json;
 mod health_check;
 mod newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
 
+pub use admin::*;
+pub use form_or_json::*;
 pub use health_check::*;
 pub use newsletters::*;
 pub use subscriptions::*;
@@ -1,8 +1,15 @@
+use crate::audit::{record_audit_event, AuditEvent};
+use crate::authentication::{Authorized, CanPublishNewsletters};
 use crate::domain::SubscriberEmail;
 use crate::email_client::EmailClient;
+use crate::metrics::Metrics;
+use crate::problem_details::Problem;
+use crate::redaction::Redacted;
+use crate::request_id::RequestId;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
+use prometheus::IntGauge;
 use sqlx::PgPool;
 
 struct ConfirmedSubscriber {
@@ -23,12 +30,37 @@ impl ResponseError for PublishError {
     }
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 pub struct BodyData {
     title: String,
     content: String,
 }
 
+/// Keeps the delivery queue depth up to date, even when publishing stops early.
+struct QueuedDeliveries<'a> {
+    depth: &'a IntGauge,
+    remaining: i64,
+}
+
+impl<'a> QueuedDeliveries<'a> {
+    fn new(depth: &'a IntGauge, count: usize) -> Self {
+        let remaining = count as i64;
+        depth.add(remaining);
+        Self { depth, remaining }
+    }
+
+    fn take_one(&mut self) {
+        self.remaining -= 1;
+        self.depth.dec();
+    }
+}
+
+impl Drop for QueuedDeliveries<'_> {
+    fn drop(&mut self) {
+        self.depth.sub(self.remaining);
+    }
+}
+
 #[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
 async fn get_confirmed_subscribers(
     pool: &PgPool,
@@ -40,28 +72,60 @@ async fn get_confirmed_subscribers(
             .into_iter()
             .map(|r| match SubscriberEmail::parse(r.email) {
                 Ok(email) => Ok(ConfirmedSubscriber { email }),
-                Err(error) => Err(anyhow::anyhow!(error)),
+                Err(error) => Err(anyhow::Error::new(error)),
             })
             .collect();
     Ok(confirmed_subscribers)
 }
 
+/// Sends an issue to every confirmed subscriber.
+#[utoipa::path(
+    post,
+    path = "/newsletters",
+    tag = "newsletters",
+    request_body = BodyData,
+    security(("basic" = []), ("session" = []), ("api_key" = [])),
+    responses(
+        (status = 200, description = "Sent to every confirmed subscriber."),
+        (status = 401, description = "Missing or invalid credentials.", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The role or the scopes of the API key do not allow it.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(user, request_id, body, pool, email_client, metrics),
+    fields(username = %user.username)
+)]
 pub async fn publish_newsletter(
+    user: Authorized<CanPublishNewsletters>,
+    request_id: RequestId,
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
+    metrics: web::Data<Metrics>,
 ) -> Result<HttpResponse, PublishError> {
+    let event = AuditEvent::new(&user.username, "newsletter.publish")
+        .request_id(request_id)
+        .after(serde_json::json!(body.0));
+    record_audit_event(pool.get_ref(), event)
+        .await
+        .context("Failed to record the newsletter issue in the audit log")?;
     let subscribers = get_confirmed_subscribers(&pool)
         .await
         .context("Failed to fetch confirmed subscribers")?;
+    let mut queue = QueuedDeliveries::new(&metrics.delivery_queue_depth, subscribers.len());
     for subscriber in subscribers {
+        queue.take_one();
         match subscriber {
             Ok(subscriber) => {
                 email_client
                     .create_email(&subscriber.email, &body.title, &body.content)
                     .await
                     .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", &subscriber.email)
+                        format!(
+                            "Failed to send newsletter issue to {}",
+                            Redacted(&subscriber.email)
+                        )
                     })?;
             }
             Err(error) => {
@@ -1,5 +1,13 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::bot_protection::{BotCheckError, BotProtection, BotSignals};
+use crate::domain::{
+    EmailCanonicalizer, FieldValidationError, NewSubscriber, SubscriberName, ValidationErrors,
+};
 use crate::email_client::EmailClient;
+use crate::email_validation::EmailDomainPolicy;
+use crate::metrics::Metrics;
+use crate::problem_details::Problem;
+use crate::redaction::Redacted;
+use crate::routes::FormOrJson;
 use crate::startup::ApplicationBaseUrl;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
@@ -12,8 +20,10 @@ use uuid::Uuid;
 
 #[derive(Debug, thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    #[error(transparent)]
+    ValidationError(ValidationErrors),
+    #[error(transparent)]
+    BotDetected(BotCheckError),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -21,19 +31,113 @@ pub enum SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match &self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_) | SubscribeError::BotDetected(_) => {
+                StatusCode::BAD_REQUEST
+            }
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(errors) => {
+                HttpResponse::build(self.status_code()).json(errors)
+            }
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
 }
 
-#[derive(serde::Deserialize)]
+impl From<BotCheckError> for SubscribeError {
+    fn from(e: BotCheckError) -> Self {
+        match e {
+            BotCheckError::UnexpectedError(e) => Self::UnexpectedError(e),
+            e => Self::BotDetected(e),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct FormData {
+    // Missing fields are reported like empty ones, as validation errors.
+    #[serde(default)]
     email: String,
+    #[serde(default)]
     name: String,
+    /// The honeypot: hidden from people, so only bots fill it in.
+    #[serde(default)]
+    website: Option<String>,
+    /// Issued by `GET /subscriptions/form-token` when the form is rendered.
+    #[serde(default)]
+    form_token: Option<String>,
+    #[serde(default)]
+    captcha_response: Option<String>,
 }
 
-fn generate_subscription_token() -> String {
+impl FormData {
+    fn bot_signals(&self) -> BotSignals<'_> {
+        BotSignals {
+            honeypot: self.website.as_deref(),
+            form_token: self.form_token.as_deref(),
+            captcha_response: self.captcha_response.as_deref(),
+        }
+    }
+
+    /// Reports every invalid field, not just the first one.
+    pub fn new_subscriber(
+        self,
+        canonicalizer: &EmailCanonicalizer,
+    ) -> Result<NewSubscriber, ValidationErrors> {
+        let name = SubscriberName::parse(self.name).map_err(|e| e.for_field("name"));
+        let email = canonicalizer
+            .parse(self.email)
+            .map_err(|e| e.for_field("email"));
+        match (name, email) {
+            (Ok(name), Ok(email)) => Ok(NewSubscriber { email, name }),
+            (name, email) => Err(ValidationErrors {
+                errors: name.err().into_iter().chain(email.err()).collect(),
+            }),
+        }
+    }
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct SubscriptionResponse {
+    /// The subscription created by the request. Requests for an address that
+    /// is already stored get a random one, which identifies nothing.
+    id: Uuid,
+    #[schema(example = "pending_confirmation")]
+    status: &'static str,
+}
+
+impl SubscriptionResponse {
+    fn pending(id: Uuid) -> HttpResponse {
+        HttpResponse::Ok().json(Self {
+            id,
+            status: "pending_confirmation",
+        })
+    }
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct FormTokenResponse {
+    form_token: String,
+}
+
+/// Hands out the token to embed in the subscription form when rendering it.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/form-token",
+    tag = "subscriptions",
+    responses((status = 200, description = "OK.", body = FormTokenResponse))
+)]
+pub async fn subscription_form_token(bot_protection: web::Data<BotProtection>) -> HttpResponse {
+    HttpResponse::Ok().json(FormTokenResponse {
+        form_token: bot_protection.issue_form_token(),
+    })
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -63,36 +167,99 @@ pub async fn create_and_send_confirmation_email(
         .await
 }
 
+/// Subscribes to the newsletter and sends the email confirming the subscription.
+///
+/// Subscribing again with an address already stored, up to its canonical form,
+/// sends a new confirmation if it is still pending and nothing otherwise. The
+/// response is the same either way, so that subscriptions cannot be probed.
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(
+        content(
+            (FormData = "application/x-www-form-urlencoded"),
+            (FormData = "application/json"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "Pending until confirmed from the email.", body = SubscriptionResponse),
+        (status = 400, description = "Invalid fields are listed in `errors`.", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "The body is neither a form nor JSON.", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "Too many requests: retry after `Retry-After` seconds.", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 #[tracing::instrument(
     name="Adding a new subscriber.",
-    skip(form, db_pool, email_client, base_url),
+    skip(form, db_pool, email_client, base_url, bot_protection, email_policy, canonicalizer, metrics),
     fields(
-        subscriber_email=%form.email,
-        subscriber_name=%form.name
+        subscriber_email=%Redacted(&form.0.email),
+        subscriber_name=%Redacted(&form.0.name)
     )
 )]
+// One argument per extractor.
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    form: FormOrJson<FormData>,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    bot_protection: web::Data<BotProtection>,
+    email_policy: web::Data<EmailDomainPolicy>,
+    canonicalizer: web::Data<EmailCanonicalizer>,
+    metrics: web::Data<Metrics>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let form_token_use = match bot_protection.check(form.0.bot_signals()).await {
+        // Bots are not told they were caught, so that they do not adapt.
+        Err(BotCheckError::HoneypotFilled) => {
+            tracing::warn!(target: "security", "Dropped a subscription filling in the honeypot");
+            return Ok(SubscriptionResponse::pending(Uuid::new_v4()));
+        }
+        result => result?,
+    };
+    let new_subscriber = form
+        .0
+        .new_subscriber(&canonicalizer)
+        .map_err(SubscribeError::ValidationError)?;
+    email_policy
+        .check(&new_subscriber.email)
+        .await
+        .map_err(|e| SubscribeError::ValidationError(e.for_field("email").into()))?;
     let mut transaction = db_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    // Only used up now that the submission is valid, so that it can be fixed and resubmitted.
+    if let Some(form_token_use) = form_token_use {
+        form_token_use.record(&mut transaction).await?;
+    }
+    let inserted_id = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
-        .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+    let (subscriber_id, status) = match inserted_id {
+        Some(subscriber_id) => (subscriber_id, "pending_confirmation".to_string()),
+        None => get_subscriber_by_canonical_email(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to look up an existing subscriber.")?,
+    };
+    // The stored subscriber is not disclosed: their id is replaced with a random one.
+    let response_id = inserted_id.unwrap_or_else(Uuid::new_v4);
+    let subscription_token = (status == "pending_confirmation").then(generate_subscription_token);
+    if let Some(subscription_token) = &subscription_token {
+        store_token(&mut transaction, subscriber_id, subscription_token)
+            .await
+            .context("Failed to store the confirmation token for a new subscriber.")?;
+    }
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    if inserted_id.is_some() {
+        metrics.subscriptions_created.inc();
+    }
+    let Some(subscription_token) = subscription_token else {
+        return Ok(SubscriptionResponse::pending(response_id));
+    };
     create_and_send_confirmation_email(
         &email_client,
         new_subscriber,
@@ -101,7 +268,7 @@ pub async fn subscribe(
     )
     .await
     .context("Failed to send a confirmation email.")?;
-    Ok(HttpResponse::Ok().finish())
+    Ok(SubscriptionResponse::pending(response_id))
 }
 
 #[tracing::instrument(
@@ -111,22 +278,46 @@ pub async fn subscribe(
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    let query = sqlx::query!(
-        r#"insert into subscriptions(id, email, name, subscribed_at, status)
-        values ($1, $2, $3, $4, 'pending_confirmation')
+    let inserted = sqlx::query!(
+        r#"insert into subscriptions(id, email, email_canonical, name, subscribed_at, status)
+        values ($1, $2, $3, $4, $5, 'pending_confirmation')
+        on conflict (email_canonical) do nothing
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.canonical(),
         new_subscriber.name.as_ref(),
         Utc::now()
-    );
-    transaction.execute(query).await.map_err(|e| {
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
-    Ok(subscriber_id)
+    })?
+    .rows_affected();
+    Ok((inserted > 0).then_some(subscriber_id))
+}
+
+/// Returns the id and status of the subscriber already stored under the
+/// canonical form of the address of `new_subscriber`.
+#[tracing::instrument(
+    name = "Get an existing subscriber from the database",
+    skip(new_subscriber, transaction)
+)]
+async fn get_subscriber_by_canonical_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<(Uuid, String), sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT id, status FROM subscriptions WHERE email_canonical = $1",
+        new_subscriber.email.canonical(),
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok((row.id, row.status))
 }
 
 #[tracing::instrument(
@@ -146,12 +337,3 @@ pub async fn store_token(
     transaction.execute(query).await?;
     Ok(())
 }
-
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(NewSubscriber { email, name })
-    }
-}
@@ -1,3 +1,4 @@
+use crate::metrics::Metrics;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
@@ -26,30 +27,35 @@ impl ResponseError for ConfirmError {
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool, metrics))]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
 ) -> Result<HttpResponse, ConfirmError> {
     let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
         .await
         .context("Failed to retrieve the subscriber id associated with the provided token.")?
         .ok_or(ConfirmError::UnknownToken)?;
-    confirm_subscriber(&pool, subscriber_id)
+    let newly_confirmed = confirm_subscriber(&pool, subscriber_id)
         .await
         .context("Failed to update the subscriber status to `confirmed`.")?;
+    if newly_confirmed {
+        metrics.subscriptions_confirmed.inc();
+    }
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Returns whether the subscriber was still pending: links can be followed twice.
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status <> 'confirmed'",
         subscriber_id
     )
     .execute(pool)
     .await?;
-    Ok(())
+    Ok(result.rows_affected() > 0)
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
@@ -1,8 +1,26 @@
-use crate::configuration::{DatabasesSettings, Settings};
+use crate::authentication::{LoginThrottle, PasswordPolicy};
+use crate::bot_protection::{sweep_used_form_tokens, BotProtection};
+use crate::configuration::{DatabasesSettings, HealthSettings, RateLimitStoreKind, Settings};
+use crate::domain::EmailCanonicalizer;
 use crate::email_client::EmailClient;
-use crate::routes::{confirm, health_check, publish_newsletter, subscribe};
+use crate::email_validation::EmailDomainPolicy;
+use crate::metrics::{metrics_endpoint, record_http_metrics, Metrics};
+use crate::openapi::{openapi_json, API_V1};
+use crate::problem_details::problem_details;
+use crate::rate_limit::{rate_limit, sweep_full_buckets, RateLimitStore, RateLimiter};
+use crate::request_id::{request_id, RequestIdRootSpanBuilder};
+use crate::routes::{
+    accept_invitation, accept_invitation_form, change_password, confirm, confirm_totp,
+    delete_api_key, enroll_totp, export_subscribers, gdpr_access, gdpr_erasure, get_api_keys,
+    get_audit_log, get_log_filter, health_check, import_subscribers_csv, invite_user, login,
+    login_second_factor, logout, post_api_key, publish_newsletter, put_log_filter, readiness_check,
+    request_password_reset, reset_password, reset_password_form, subscribe,
+    subscription_form_token, ErasureKey,
+};
 use actix_web::dev::Server;
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
+use anyhow::Context;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
@@ -11,45 +29,118 @@ use tracing_actix_web::TracingLogger;
 pub struct Application {
     port: u16,
     server: Server,
+    /// Set when the metrics are served on their own admin port.
+    metrics_server: Option<(u16, Server)>,
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
+/// Defences against abuse: password guessing, weak passwords, signup spam and
+/// junk addresses.
+pub struct Safeguards {
+    pub login_throttle: LoginThrottle,
+    pub password_policy: PasswordPolicy,
+    pub rate_limiter: RateLimiter,
+    pub bot_protection: BotProtection,
+    pub email_policy: EmailDomainPolicy,
+    /// Tells the aliases of a mailbox apart from other addresses.
+    pub email_canonicalizer: EmailCanonicalizer,
+}
+
+/// What operators watch the application with.
+pub struct Monitoring {
+    pub health: HealthSettings,
+    pub metrics: Metrics,
+    /// Whether `/metrics` is served alongside the API.
+    pub serve_metrics: bool,
+}
+
 impl Application {
-    pub async fn build(configuration: &Settings) -> Result<Self, std::io::Error> {
+    pub async fn build(configuration: &Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let sender_email = configuration
+        let email_client = configuration
             .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            &configuration.email_client.base_url,
-            sender_email,
-            &configuration.email_client.authorization_token,
-            timeout,
-        );
+            .client()
+            .context("Failed to build the email client.")?;
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+        let rate_limit_store = match configuration.rate_limit.store {
+            RateLimitStoreKind::Memory => RateLimitStore::Memory(Default::default()),
+            RateLimitStoreKind::Postgres => {
+                tokio::spawn(sweep_full_buckets(connection_pool.clone()));
+                RateLimitStore::Postgres(connection_pool.clone())
+            }
+        };
+        tokio::spawn(sweep_used_form_tokens(connection_pool.clone()));
+        let email_canonicalizer = configuration.email_validation.canonicalizer();
+        let safeguards = Safeguards {
+            login_throttle: LoginThrottle::new(configuration.login_protection.clone()),
+            password_policy: configuration.password_policy.policy()?,
+            rate_limiter: RateLimiter::new(
+                configuration.rate_limit.clone(),
+                rate_limit_store,
+                email_canonicalizer,
+            ),
+            bot_protection: BotProtection::new(
+                &configuration.bot_protection,
+                connection_pool.clone(),
+            ),
+            email_policy: configuration.email_validation.policy()?,
+            email_canonicalizer,
+        };
+        let metrics = Metrics::new(email_client.metrics());
+        let metrics_server = match configuration.metrics.admin_port {
+            Some(admin_port) => {
+                let address = format!("{}:{admin_port}", configuration.application.host);
+                let listener = TcpListener::bind(address)?;
+                let port = listener.local_addr().unwrap().port();
+                let server = run_metrics(listener, connection_pool.clone(), metrics.clone())?;
+                Some((port, server))
+            }
+            None => None,
+        };
+        let monitoring = Monitoring {
+            health: configuration.health.clone(),
+            metrics,
+            serve_metrics: metrics_server.is_none(),
+        };
         let server = run(
             listener,
             connection_pool,
             email_client,
             &configuration.application.base_url,
+            ErasureKey::new(&configuration.gdpr, email_canonicalizer),
+            safeguards,
+            monitoring,
         )?;
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            metrics_server,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// The admin port serving the metrics, if they are not served on [`Self::port`].
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics_server.as_ref().map(|(port, _)| *port)
+    }
+
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        match self.metrics_server {
+            Some((_, metrics_server)) => {
+                tokio::try_join!(self.server, metrics_server)?;
+                Ok(())
+            }
+            None => self.server.await,
+        }
     }
 }
 
@@ -58,26 +149,152 @@ pub fn run(
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: &String,
+    erasure_key: ErasureKey,
+    safeguards: Safeguards,
+    monitoring: Monitoring,
 ) -> Result<Server, std::io::Error> {
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url.to_owned()));
+    let erasure_key = web::Data::new(erasure_key);
+    let login_throttle = web::Data::new(safeguards.login_throttle);
+    let password_policy = web::Data::new(safeguards.password_policy);
+    let rate_limiter = web::Data::new(safeguards.rate_limiter);
+    let bot_protection = web::Data::new(safeguards.bot_protection);
+    let email_policy = web::Data::new(safeguards.email_policy);
+    let email_canonicalizer = web::Data::new(safeguards.email_canonicalizer);
+    let health = web::Data::new(monitoring.health);
+    let metrics = web::Data::new(monitoring.metrics);
+    let serve_metrics = monitoring.serve_metrics;
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(TracingLogger::default())
+            .wrap(from_fn(problem_details))
+            .wrap(from_fn(record_http_metrics))
+            .wrap(TracingLogger::<RequestIdRootSpanBuilder>::new())
+            .wrap(from_fn(request_id))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(erasure_key.clone())
+            .app_data(login_throttle.clone())
+            .app_data(password_policy.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(bot_protection.clone())
+            .app_data(email_policy.clone())
+            .app_data(email_canonicalizer.clone())
+            .app_data(health.clone())
+            .app_data(metrics.clone())
+            .configure(|cfg| {
+                if serve_metrics {
+                    cfg.route("/metrics", web::get().to(metrics_endpoint));
+                }
+            })
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
-            .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/health/live", web::get().to(health_check))
+            .route("/health/ready", web::get().to(readiness_check))
+            // Linked from confirmation emails, so it stays where it is.
+            .service(
+                web::resource("/subscriptions/confirm")
+                    .wrap(from_fn(rate_limit))
+                    .route(web::get().to(confirm)),
+            )
+            // The pages opened by the links of password reset and invitation
+            // emails. Their forms post to the unversioned routes below.
+            .route(
+                "/admin/password/reset/confirm",
+                web::get().to(reset_password_form),
+            )
+            .route(
+                "/admin/invitations/accept",
+                web::get().to(accept_invitation_form),
+            )
+            .service(
+                web::scope(API_V1)
+                    .route("/openapi.json", web::get().to(openapi_json))
+                    .configure(api_v1),
+            )
+            // The unversioned routes predate `/api/v1` and are kept for the
+            // clients still using them. They are not documented.
+            .configure(api_v1)
+    })
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
+
+/// Serves `/metrics` on its own, so that it can be kept off the public port.
+fn run_metrics(
+    listener: TcpListener,
+    db_pool: PgPool,
+    metrics: Metrics,
+) -> Result<Server, std::io::Error> {
+    let db_pool = web::Data::new(db_pool);
+    let metrics = web::Data::new(metrics);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .app_data(db_pool.clone())
+            .app_data(metrics.clone())
+            .route("/metrics", web::get().to(metrics_endpoint))
     })
     .listen(listener)?
     .run();
     Ok(server)
 }
 
+/// The JSON API, as documented by [`ApiDocV1`](crate::openapi::ApiDocV1).
+fn api_v1(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/subscriptions")
+            .wrap(from_fn(rate_limit))
+            .route(web::post().to(subscribe)),
+    )
+    .route(
+        "/subscriptions/form-token",
+        web::get().to(subscription_form_token),
+    )
+    .route("/newsletters", web::post().to(publish_newsletter))
+    .route(
+        "/admin/subscribers/import",
+        web::post().to(import_subscribers_csv),
+    )
+    .route(
+        "/admin/subscribers/export",
+        web::get().to(export_subscribers),
+    )
+    .route("/admin/gdpr/access", web::post().to(gdpr_access))
+    .route("/admin/gdpr/erasure", web::post().to(gdpr_erasure))
+    .route("/admin/audit", web::get().to(get_audit_log))
+    .route("/admin/log-filter", web::get().to(get_log_filter))
+    .route("/admin/log-filter", web::put().to(put_log_filter))
+    .route("/admin/login", web::post().to(login))
+    .route(
+        "/admin/login/second-factor",
+        web::post().to(login_second_factor),
+    )
+    .route("/admin/logout", web::post().to(logout))
+    .route("/admin/2fa/enroll", web::post().to(enroll_totp))
+    .route("/admin/2fa/confirm", web::post().to(confirm_totp))
+    .route("/admin/api-keys", web::post().to(post_api_key))
+    .route("/admin/api-keys", web::get().to(get_api_keys))
+    .route("/admin/api-keys/{id}", web::delete().to(delete_api_key))
+    .route("/admin/password", web::post().to(change_password))
+    .service(
+        web::resource("/admin/password/reset")
+            .wrap(from_fn(rate_limit))
+            .route(web::post().to(request_password_reset)),
+    )
+    .route(
+        "/admin/password/reset/confirm",
+        web::post().to(reset_password),
+    )
+    .route("/admin/users/invitations", web::post().to(invite_user))
+    .route(
+        "/admin/invitations/accept",
+        web::post().to(accept_invitation),
+    );
+}
+
 pub fn get_connection_pool(configuration: &DatabasesSettings) -> PgPool {
     PgPoolOptions::new().connect_lazy_with(configuration.pg_connection())
 }
@@ -1,28 +1,147 @@
+use crate::configuration::{LogFormat, LoggingSettings, TracingSettings};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{ExporterBuildError, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::sync::OnceLock;
+use tokio::task::JoinHandle;
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::InitError;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::fmt::MakeWriter;
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, EnvFilter, Registry};
 
+/// Spans are exported over OTLP/HTTP when a collector is configured. Without
+/// one they are still assigned OpenTelemetry trace IDs, so that incoming
+/// trace context is carried through to the logs and outgoing requests.
+pub fn get_tracer_provider(
+    name: &str,
+    settings: &TracingSettings,
+) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let resource = Resource::builder()
+        .with_service_name(name.to_owned())
+        .build();
+    let mut builder = SdkTracerProvider::builder().with_resource(resource);
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+            .with_timeout(settings.export_timeout())
+            .build()?;
+        builder = builder.with_batch_exporter(exporter);
+    }
+    Ok(builder.build())
+}
+
+/// The filter of the global subscriber, which admins can change at runtime.
+pub struct LogFilter(reload::Handle<EnvFilter, Registry>);
+
+impl LogFilter {
+    /// The directives in effect, e.g. `info,sqlx=warn`.
+    pub fn current(&self) -> Result<String, reload::Error> {
+        self.0.with_current(|filter| filter.to_string())
+    }
+
+    pub fn replace(&self, filter: EnvFilter) -> Result<(), reload::Error> {
+        self.0.reload(filter)
+    }
+}
+
+static LOG_FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// The filter of the subscriber set up by [`init_subscriber`], if any.
+pub fn log_filter() -> Option<&'static LogFilter> {
+    LOG_FILTER.get()
+}
+
+/// Writes logs to `sink` and, if configured, to rotating files. Also returns
+/// the handle changing the filter of the subscriber and, with files, the guard
+/// of the thread writing them: logs still buffered are flushed when it drops,
+/// so keep it alive for as long as the subscriber.
 pub fn get_subscriber<Sink>(
     name: String,
-    env_filter: String,
+    settings: &LoggingSettings,
     sink: Sink,
-) -> impl Subscriber + Send + Sync
+    tracer_provider: &SdkTracerProvider,
+) -> Result<
+    (
+        impl Subscriber + Send + Sync,
+        LogFilter,
+        Option<WorkerGuard>,
+    ),
+    InitError,
+>
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name, sink);
-    Registry::default()
-        .with(env_filter)
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&settings.filter));
+    let (filter_layer, filter_handle) = reload::Layer::new(env_filter);
+    let otel_layer =
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(name.clone()));
+    // Requests do not wait on the disk: a dedicated thread writes the files.
+    let (file_layer, file_guard) = match &settings.file {
+        Some(file) => {
+            let (writer, guard) = tracing_appender::non_blocking(file.appender()?);
+            let layer = output_layer(file.format, name.clone(), writer, false);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    let subscriber = Registry::default()
+        .with(filter_layer)
+        .with(otel_layer)
         .with(JsonStorageLayer)
-        .with(formatting_layer)
+        .with(output_layer(settings.format, name, sink, true))
+        .with(file_layer);
+    Ok((subscriber, LogFilter(filter_handle), file_guard))
 }
 
-pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
+fn output_layer<S, W>(
+    format: LogFormat,
+    name: String,
+    writer: W,
+    ansi: bool,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Json => BunyanFormattingLayer::new(name, writer).boxed(),
+        LogFormat::Pretty => tracing_subscriber::fmt::layer()
+            .pretty()
+            .with_ansi(ansi)
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Compact => tracing_subscriber::fmt::layer()
+            .compact()
+            .with_ansi(ansi)
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+/// Also installs the W3C `traceparent` propagator, used both to continue the
+/// traces of incoming requests and to pass them on to the email provider.
+pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync, log_filter: LogFilter) {
     LogTracer::init().expect("Failed to set logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let _ = LOG_FILTER.set(log_filter);
+}
+
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
@@ -0,0 +1,318 @@
+use crate::helpers::{spawn_app, test_app, token_from_link, TestApp};
+use rstest::*;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::authentication::{generate_token, hash_token};
+use zero2prod::configuration::TokenBucketSettings;
+
+const NEW_PASSWORD: &str = "correct horse battery staple";
+
+async fn set_test_user_email(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        email,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn request_reset_link(app: &TestApp) -> reqwest::Url {
+    let sent = app.email_server.received_requests().await.unwrap().len();
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_json(
+            "/admin/password/reset",
+            serde_json::json!({ "username": app.test_user.username }),
+        )
+        .await;
+    assert_eq!(202, response.status().as_u16());
+    let email_request = &app.received_emails(sent + 1).await[sent];
+    app.get_confirmation_link(email_request)
+}
+
+async fn session_count(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM admin_sessions WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn log_in(app: &TestApp) {
+    app.post_json(
+        "/admin/login",
+        serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+async fn credentials_are_valid(app: &TestApp, password: &str) -> bool {
+    reqwest::Client::new()
+        .get(format!("{}/api/v1/admin/audit", &app.address))
+        .basic_auth(&app.test_user.username, Some(password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .status()
+        .is_success()
+}
+
+#[rstest]
+#[tokio::test]
+async fn changing_the_password_replaces_the_credentials(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = app
+        .post_change_password(serde_json::json!({
+            "current_password": app.test_user.password,
+            "new_password": NEW_PASSWORD,
+        }))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(credentials_are_valid(&app, NEW_PASSWORD).await);
+    assert!(!credentials_are_valid(&app, &app.test_user.password).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn changing_the_password_ends_the_sessions_of_the_user(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    log_in(&app).await;
+    assert_eq!(session_count(&app).await, 1);
+
+    app.post_change_password(serde_json::json!({
+        "current_password": app.test_user.password,
+        "new_password": NEW_PASSWORD,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(session_count(&app).await, 0);
+}
+
+#[rstest]
+#[case(serde_json::json!({ "current_password": "wrong-password", "new_password": NEW_PASSWORD }), "wrong current password")]
+#[case(serde_json::json!({ "current_password": "", "new_password": "short" }), "too short")]
+#[case(serde_json::json!({ "current_password": "", "new_password": "Password123456" }), "breached")]
+#[tokio::test]
+async fn invalid_password_changes_are_rejected(
+    #[future] test_app: TestApp,
+    #[case] mut body: serde_json::Value,
+    #[case] description: &str,
+) {
+    let app = test_app.await;
+    if body["current_password"] == "" {
+        body["current_password"] = app.test_user.password.clone().into();
+    }
+
+    let response = app.post_change_password(body).await;
+
+    assert_eq!(
+        400,
+        response.status().as_u16(),
+        "The API did not reject a password change that was {description}."
+    );
+    assert!(credentials_are_valid(&app, &app.test_user.password).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_password_reset_link_can_be_used_once(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    let token = token_from_link(&request_reset_link(&app).await);
+    let body = serde_json::json!({ "token": token, "new_password": NEW_PASSWORD });
+
+    let first = app
+        .post_json("/admin/password/reset/confirm", body.clone())
+        .await;
+    let second = app.post_json("/admin/password/reset/confirm", body).await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(401, second.status().as_u16());
+    assert!(credentials_are_valid(&app, NEW_PASSWORD).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn expired_password_reset_links_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    let token = token_from_link(&request_reset_link(&app).await);
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_json(
+            "/admin/password/reset/confirm",
+            serde_json::json!({ "token": token, "new_password": NEW_PASSWORD }),
+        )
+        .await;
+
+    assert_eq!(401, response.status().as_u16());
+    assert!(credentials_are_valid(&app, &app.test_user.password).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn reset_requests_for_unknown_users_look_the_same(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_json(
+            "/admin/password/reset",
+            serde_json::json!({ "username": "nobody" }),
+        )
+        .await;
+
+    assert_eq!(202, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn the_reset_link_opens_a_form_resetting_the_password(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    let link = request_reset_link(&app).await;
+    let token = token_from_link(&link);
+
+    let page = reqwest::get(link).await.unwrap();
+
+    assert_eq!(200, page.status().as_u16());
+    assert_eq!("text/html; charset=utf-8", page.headers()["Content-Type"]);
+    let html = page.text().await.unwrap();
+    assert!(html.contains(r#"action="/admin/password/reset/confirm" method="post""#));
+    assert!(html.contains(&format!(r#"name="token" value="{token}""#)));
+    let response = app
+        .post_form(
+            "/admin/password/reset/confirm",
+            &[("token", &token), ("new_password", NEW_PASSWORD)],
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    assert!(credentials_are_valid(&app, NEW_PASSWORD).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_password_reset_revokes_sessions_and_other_reset_links(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    log_in(&app).await;
+    let first = token_from_link(&request_reset_link(&app).await);
+    // Only one link is emailed at a time, so the other one is stored directly.
+    let second = generate_token();
+    sqlx::query!(
+        r#"INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, now(), now() + interval '1 hour')"#,
+        hash_token(&second),
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.post_json(
+        "/admin/password/reset/confirm",
+        serde_json::json!({ "token": first, "new_password": NEW_PASSWORD }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(session_count(&app).await, 0);
+    let response = app
+        .post_json(
+            "/admin/password/reset/confirm",
+            serde_json::json!({ "token": second, "new_password": "another good passphrase" }),
+        )
+        .await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn reset_requests_do_not_wait_for_the_email_provider(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    Mock::given(path("/v1.0/me/messages"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_json(
+            "/admin/password/reset",
+            serde_json::json!({ "username": app.test_user.username }),
+        )
+        .await;
+
+    assert_eq!(202, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn no_other_reset_link_is_sent_while_one_is_valid(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_json(
+                "/admin/password/reset",
+                serde_json::json!({ "username": app.test_user.username }),
+            )
+            .await;
+        assert_eq!(202, response.status().as_u16());
+    }
+
+    app.received_emails(1).await;
+}
+
+#[tokio::test]
+async fn reset_requests_are_rate_limited() {
+    let app = spawn_app(|c| {
+        c.rate_limit.per_ip = TokenBucketSettings {
+            capacity: 2,
+            refill_per_minute: 1,
+        }
+    })
+    .await;
+    let body = serde_json::json!({ "username": "nobody" });
+
+    for _ in 0..2 {
+        let response = app.post_json("/admin/password/reset", body.clone()).await;
+        assert_eq!(202, response.status().as_u16());
+    }
+    let response = app.post_json("/admin/password/reset", body).await;
+
+    assert_eq!(429, response.status().as_u16());
+}
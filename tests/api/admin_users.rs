@@ -0,0 +1,196 @@
+use crate::helpers::{test_app, token_from_link, TestApp};
+use rstest::*;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::authentication::Role;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+async fn invite(app: &TestApp, email: &str, role: &str) -> String {
+    token_from_link(&invitation_link(app, email, role).await)
+}
+
+async fn invitation_link(app: &TestApp, email: &str, role: &str) -> reqwest::Url {
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_invitation(&app.test_user, email, role).await;
+    assert_eq!(200, response.status().as_u16());
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_link(email_request)
+}
+
+#[rstest]
+#[tokio::test]
+async fn an_accepted_invitation_creates_a_user_with_the_invited_role(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let token = invite(&app, "ursula@example.com", "editor").await;
+
+    let response = app
+        .post_json(
+            "/admin/invitations/accept",
+            serde_json::json!({ "token": token, "username": "ursula", "password": PASSWORD }),
+        )
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let user = sqlx::query!("SELECT email, role FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the new user.");
+    assert_eq!(user.email.as_deref(), Some("ursula@example.com"));
+    assert_eq!(user.role, "editor");
+    let export = reqwest::Client::new()
+        .get(format!("{}/api/v1/admin/subscribers/export", &app.address))
+        .basic_auth("ursula", Some(PASSWORD))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, export.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn an_invitation_can_only_be_accepted_once(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let token = invite(&app, "ursula@example.com", "viewer").await;
+    let accept = |username: &str| {
+        app.post_json(
+            "/admin/invitations/accept",
+            serde_json::json!({ "token": token, "username": username, "password": PASSWORD }),
+        )
+    };
+
+    assert_eq!(200, accept("ursula").await.status().as_u16());
+    assert_eq!(401, accept("mallory").await.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn weak_passwords_do_not_consume_the_invitation(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let token = invite(&app, "ursula@example.com", "viewer").await;
+
+    let weak = app
+        .post_json(
+            "/admin/invitations/accept",
+            serde_json::json!({ "token": token, "username": "ursula", "password": "ursula-is-great" }),
+        )
+        .await;
+    let strong = app
+        .post_json(
+            "/admin/invitations/accept",
+            serde_json::json!({ "token": token, "username": "ursula", "password": PASSWORD }),
+        )
+        .await;
+
+    assert_eq!(400, weak.status().as_u16());
+    assert_eq!(200, strong.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn only_admins_can_invite_users(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let publisher = app.create_user(Role::Publisher).await;
+
+    let response = app
+        .post_invitation(&publisher, "ursula@example.com", "admin")
+        .await;
+
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn invitations_with_an_unknown_role_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = app
+        .post_invitation(&app.test_user, "ursula@example.com", "owner")
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn an_invitation_that_was_not_delivered_is_not_stored(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_invitation(&app.test_user, "ursula@example.com", "editor")
+        .await;
+
+    assert_eq!(500, response.status().as_u16());
+    let stored = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM user_invitations"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(0, stored);
+}
+
+#[rstest]
+#[tokio::test]
+async fn accepting_an_invitation_is_audited(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let token = invite(&app, "ursula@example.com", "viewer").await;
+    app.post_json(
+        "/admin/invitations/accept",
+        serde_json::json!({ "token": token, "username": "ursula", "password": PASSWORD }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let entries: serde_json::Value = app
+        .get_audit_log(&[("action", "user.create")])
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["actor"], "ursula");
+    assert_eq!(entries[0]["after"]["role"], "viewer");
+}
+
+#[rstest]
+#[tokio::test]
+async fn the_invitation_link_opens_a_form_creating_the_user(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let link = invitation_link(&app, "ursula@example.com", "viewer").await;
+    let token = token_from_link(&link);
+
+    let page = reqwest::get(link).await.unwrap();
+
+    assert_eq!(200, page.status().as_u16());
+    let html = page.text().await.unwrap();
+    assert!(html.contains(r#"action="/admin/invitations/accept" method="post""#));
+    assert!(html.contains(&format!(r#"name="token" value="{token}""#)));
+    let response = app
+        .post_form(
+            "/admin/invitations/accept",
+            &[
+                ("token", &token),
+                ("username", "ursula"),
+                ("password", PASSWORD),
+            ],
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let user = sqlx::query!("SELECT role FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the new user.");
+    assert_eq!(user.role, "viewer");
+}
@@ -0,0 +1,191 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use zero2prod::authentication::Role;
+
+async fn create_api_key(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/api-keys", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Creates a key with the given scopes and returns its id and plain key.
+async fn api_key_with_scopes(app: &TestApp, scopes: &[&str]) -> (String, String) {
+    let response =
+        create_api_key(app, serde_json::json!({ "name": "cms", "scopes": scopes })).await;
+    assert_eq!(201, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    (
+        body["id"].as_str().unwrap().to_string(),
+        body["key"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn publish_with_key(app: &TestApp, key: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .bearer_auth(key)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn list_api_keys(app: &TestApp) -> Vec<serde_json::Value> {
+    reqwest::Client::new()
+        .get(format!("{}/api/v1/admin/api-keys", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[rstest]
+#[tokio::test]
+async fn an_api_key_can_do_what_its_scopes_allow(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (id, key) = api_key_with_scopes(&app, &["newsletters:publish"]).await;
+
+    let response = publish_with_key(&app, &key).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let entries: Vec<serde_json::Value> = app
+        .get_audit_log(&[("action", "newsletter.publish")])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(entries[0]["actor"], format!("api-key:{id}"));
+}
+
+#[rstest]
+#[tokio::test]
+async fn an_api_key_is_rejected_outside_of_its_scopes(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (_, key) = api_key_with_scopes(&app, &["subscribers:read"]).await;
+
+    let response = publish_with_key(&app, &key).await;
+
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn an_api_key_cannot_exceed_the_role_of_its_creator(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (_, key) = api_key_with_scopes(&app, &["newsletters:publish"]).await;
+    sqlx::query!(
+        "UPDATE users SET role = $1 WHERE user_id = $2",
+        Role::Editor.as_str(),
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = publish_with_key(&app, &key).await;
+
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn api_keys_cannot_be_used_for_human_only_endpoints(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (_, key) = api_key_with_scopes(&app, &["subscribers:read"]).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/2fa/enroll", &app.address))
+        .bearer_auth(&key)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn revoked_api_keys_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (id, key) = api_key_with_scopes(&app, &["newsletters:publish"]).await;
+
+    let response = reqwest::Client::new()
+        .delete(format!("{}/api/v1/admin/api-keys/{}", &app.address, id))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(401, publish_with_key(&app, &key).await.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn expired_api_keys_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (_, key) = api_key_with_scopes(&app, &["newsletters:publish"]).await;
+    sqlx::query!("UPDATE api_keys SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = publish_with_key(&app, &key).await;
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn listing_shows_last_use_but_never_the_key(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (id, key) = api_key_with_scopes(&app, &["newsletters:publish"]).await;
+    assert!(list_api_keys(&app).await[0]["last_used_at"].is_null());
+
+    publish_with_key(&app, &key)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let keys = list_api_keys(&app).await;
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["id"], id);
+    assert!(!keys[0]["last_used_at"].is_null());
+    assert!(key.starts_with(keys[0]["prefix"].as_str().unwrap()));
+    assert!(keys[0].get("key").is_none());
+}
+
+#[rstest]
+#[case(serde_json::json!({ "name": "cms", "scopes": [] }), "no scopes")]
+#[case(serde_json::json!({ "name": "cms", "scopes": ["users:manage"] }), "an unknown scope")]
+#[case(serde_json::json!({ "name": " ", "scopes": ["audit:read"] }), "an empty name")]
+#[case(
+    serde_json::json!({ "name": "cms", "scopes": ["audit:read"], "expires_at": "2000-01-01T00:00:00Z" }),
+    "an expiry in the past"
+)]
+#[tokio::test]
+async fn invalid_api_keys_are_rejected(
+    #[future] test_app: TestApp,
+    #[case] body: serde_json::Value,
+    #[case] description: &str,
+) {
+    let app = test_app.await;
+
+    let response = create_api_key(&app, body).await;
+
+    assert_eq!(
+        400,
+        response.status().as_u16(),
+        "The API did not reject a key with {description}."
+    );
+}
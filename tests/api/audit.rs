@@ -0,0 +1,121 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+
+#[rstest]
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::get(format!("{}/api/v1/admin/audit", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn publishing_a_newsletter_is_audited(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let response = app.get_audit_log(&[]).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let entries: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["actor"], app.test_user.username.as_str());
+    assert_eq!(entries[0]["action"], "newsletter.publish");
+    assert_eq!(entries[0]["after"]["title"], "Newsletter title");
+    assert!(entries[0]["request_id"].is_string());
+}
+
+#[rstest]
+#[tokio::test]
+async fn imports_and_erasures_are_audited_without_personal_data(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    app.post_subscribers_import(
+        "name,email\nUrsula,ursula@example.com\n".into(),
+        "confirmed",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.post_gdpr("erasure", "ursula@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app.get_audit_log(&[]).await;
+
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("ursula"));
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["action"], "subscriber.erase");
+    assert_eq!(entries[0]["before"]["erased_subscriptions"], 1);
+    assert_eq!(entries[1]["action"], "subscribers.import");
+    assert_eq!(entries[1]["after"]["imported"], 1);
+    assert_eq!(entries[1]["after"]["mode"], "confirmed");
+}
+
+#[rstest]
+#[tokio::test]
+async fn the_audit_log_can_be_filtered(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    app.post_subscribers_import("name,email\n".into(), "confirmed")
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_gdpr("erasure", "ursula@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app
+        .get_audit_log(&[("action", "subscribers.import"), ("limit", "10")])
+        .await;
+
+    let entries: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["action"], "subscribers.import");
+}
+
+#[rstest]
+#[case("0")]
+#[case("1001")]
+#[case("many")]
+#[tokio::test]
+async fn invalid_page_sizes_are_rejected(#[future] test_app: TestApp, #[case] limit: &str) {
+    let app = test_app.await;
+
+    let response = app.get_audit_log(&[("limit", limit)]).await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn audit_log_entries_cannot_be_rewritten(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    app.post_subscribers_import("name,email\n".into(), "confirmed")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let update = sqlx::query!("UPDATE audit_log SET actor = 'someone else'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+
+    assert!(update.is_err());
+    assert!(delete.is_err());
+}
@@ -0,0 +1,71 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use zero2prod::authentication::Role;
+
+async fn send_as(app: &TestApp, role: Role, method: &str, path: &str) -> reqwest::Response {
+    let user = app.create_user(role).await;
+    let method = reqwest::Method::from_bytes(method.as_bytes()).unwrap();
+    reqwest::Client::new()
+        .request(method, format!("{}{}", &app.address, path))
+        .basic_auth(&user.username, Some(&user.password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+            "email": "ursula@example.com",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[rstest]
+#[case(
+    Role::Viewer,
+    "POST",
+    "/admin/subscribers/import?mode=confirmed",
+    "editor"
+)]
+#[case(Role::Viewer, "POST", "/admin/gdpr/erasure", "editor")]
+#[case(Role::Editor, "POST", "/newsletters", "publisher")]
+#[case(Role::Publisher, "GET", "/admin/audit", "admin")]
+#[tokio::test]
+async fn roles_below_the_required_one_are_forbidden(
+    #[future] test_app: TestApp,
+    #[case] role: Role,
+    #[case] method: &str,
+    #[case] path: &str,
+    #[case] required_role: &str,
+) {
+    let app = test_app.await;
+
+    let response = send_as(&app, role, method, path).await;
+
+    assert_eq!(403, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    assert!(
+        body.contains(required_role),
+        "The error did not mention the `{required_role}` role: {body}"
+    );
+}
+
+#[rstest]
+#[case(Role::Viewer, "GET", "/admin/subscribers/export")]
+#[case(Role::Viewer, "POST", "/admin/gdpr/access")]
+#[case(Role::Editor, "POST", "/admin/subscribers/import?mode=confirmed")]
+#[case(Role::Editor, "POST", "/admin/gdpr/erasure")]
+#[case(Role::Publisher, "POST", "/newsletters")]
+#[case(Role::Admin, "POST", "/newsletters")]
+#[case(Role::Admin, "GET", "/admin/audit")]
+#[tokio::test]
+async fn roles_granting_the_permission_are_allowed(
+    #[future] test_app: TestApp,
+    #[case] role: Role,
+    #[case] method: &str,
+    #[case] path: &str,
+) {
+    let app = test_app.await;
+
+    let response = send_as(&app, role, method, path).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
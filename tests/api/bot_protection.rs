@@ -0,0 +1,167 @@
+use crate::helpers::{spawn_app, test_app, TestApp};
+use rstest::*;
+use secrecy::SecretBox;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::CaptchaSettings;
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn mock_email_server(app: &TestApp, expected_emails: u64) {
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(expected_emails)
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscribers.")
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_filled_honeypot_is_silently_dropped(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    mock_email_server(&app, 0).await;
+
+    let response = app
+        .post_subscriptions(format!("{BODY}&website=http%3A%2F%2Fspam.example"))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(0, subscriber_count(&app).await);
+}
+
+#[rstest]
+#[case(String::new(), "missing")]
+#[case("&form_token=1000.nonce.deadbeef".to_string(), "forged")]
+#[tokio::test]
+async fn a_submission_without_a_valid_form_token_is_rejected(
+    #[case] form_token: String,
+    #[case] description: &str,
+) {
+    let app = spawn_app(|c| c.bot_protection.require_form_token = true).await;
+    mock_email_server(&app, 0).await;
+
+    let response = app
+        .post_subscriptions_raw(format!("{BODY}{form_token}"))
+        .await;
+
+    assert_eq!(
+        400,
+        response.status().as_u16(),
+        "The form token was {description}."
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn clients_without_a_form_token_are_accepted_unless_it_is_required(
+    #[future] test_app: TestApp,
+) {
+    let app = test_app.await;
+    mock_email_server(&app, 1).await;
+
+    let response = app.post_subscriptions_raw(BODY.to_string()).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_form_token_is_good_for_a_single_submission(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    mock_email_server(&app, 1).await;
+    let form_token = app.get_form_token().await;
+
+    let first = app
+        .post_subscriptions_raw(format!("{BODY}&form_token={form_token}"))
+        .await;
+    let replayed = app
+        .post_subscriptions_raw(format!(
+            "name=tom&email=tom%40gmail.com&form_token={form_token}"
+        ))
+        .await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(400, replayed.status().as_u16());
+    assert_eq!(1, subscriber_count(&app).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn an_invalid_submission_does_not_use_up_its_form_token(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    mock_email_server(&app, 1).await;
+    let form_token = app.get_form_token().await;
+
+    let invalid = app
+        .post_subscriptions_raw(format!(
+            "name=le%20guin&email=not-an-email&form_token={form_token}"
+        ))
+        .await;
+    let fixed = app
+        .post_subscriptions_raw(format!("{BODY}&form_token={form_token}"))
+        .await;
+
+    assert_eq!(400, invalid.status().as_u16());
+    assert_eq!(200, fixed.status().as_u16());
+    assert_eq!(1, subscriber_count(&app).await);
+}
+
+#[tokio::test]
+async fn a_form_submitted_too_quickly_is_rejected() {
+    let app = spawn_app(|c| c.bot_protection.min_fill_seconds = 60).await;
+    mock_email_server(&app, 0).await;
+
+    let response = app.post_subscriptions(BODY.to_string()).await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(0, subscriber_count(&app).await);
+}
+
+#[tokio::test]
+async fn the_captcha_is_verified_with_the_provider() {
+    let captcha_server = MockServer::start().await;
+    let verify_url = captcha_server.uri();
+    let app = spawn_app(|c| {
+        c.bot_protection.captcha = CaptchaSettings::Http {
+            verify_url,
+            secret: SecretBox::new(Box::new("captcha-secret".to_string())),
+            timeout_milliseconds: 1000,
+        }
+    })
+    .await;
+    mock_email_server(&app, 1).await;
+    Mock::given(method("POST"))
+        .and(body_string_contains("response=solved"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true
+        })))
+        .mount(&captcha_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(body_string_contains("response=unsolved"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": false
+        })))
+        .mount(&captcha_server)
+        .await;
+
+    let unsolved = app
+        .post_subscriptions(format!("{BODY}&captcha_response=unsolved"))
+        .await;
+    let missing = app.post_subscriptions(BODY.to_string()).await;
+    let solved = app
+        .post_subscriptions(format!("{BODY}&captcha_response=solved"))
+        .await;
+
+    assert_eq!(400, unsolved.status().as_u16());
+    assert_eq!(400, missing.status().as_u16());
+    assert_eq!(200, solved.status().as_u16());
+}
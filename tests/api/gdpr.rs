@@ -0,0 +1,111 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use sha2::{Digest, Sha256};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_pending_subscriber(app: &TestApp, email: &str) {
+    let _mock_guard = Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(format!("name=Ursula&email={email}"))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[rstest]
+#[case("access")]
+#[case("erasure")]
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected(
+    #[future] test_app: TestApp,
+    #[case] operation: &str,
+) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/gdpr/{}", &app.address, operation))
+        .json(&serde_json::json!({ "email": "ursula@example.com" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn access_returns_every_stored_datum_for_the_email(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    create_pending_subscriber(&app, "ursula@example.com").await;
+
+    let response = app.post_gdpr("access", "Ursula@Example.com").await;
+
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    let subscriptions = report["subscriptions"].as_array().unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["email"], "ursula@example.com");
+    assert_eq!(subscriptions[0]["name"], "Ursula");
+    assert_eq!(subscriptions[0]["status"], "pending_confirmation");
+    let tokens = report["subscription_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["subscriber_id"], subscriptions[0]["id"]);
+}
+
+#[rstest]
+#[tokio::test]
+async fn erasure_deletes_the_subscriber_and_leaves_a_tombstone(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    create_pending_subscriber(&app, "ursula@example.com").await;
+
+    let response = app.post_gdpr("erasure", "ursula@example.com").await;
+
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["erased_subscriptions"], 1);
+    assert_eq!(report["erased_subscription_tokens"], 1);
+    let remaining = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+    let tombstone = sqlx::query!("SELECT email_hash, erased_by FROM erased_subscribers")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the erasure tombstone.");
+    assert_eq!(tombstone.erased_by, app.test_user.username);
+    assert!(!tombstone.email_hash.contains("ursula"));
+    // Keyed, so that it cannot be found by hashing candidate addresses.
+    let unkeyed_hash = hex::encode(Sha256::digest(b"ursula@example.com"));
+    assert_ne!(tombstone.email_hash, unkeyed_hash);
+    let metrics = app.get_metrics().await;
+    assert!(metrics
+        .lines()
+        .any(|line| line == "subscribers_erased_total 1"));
+}
+
+#[rstest]
+#[tokio::test]
+async fn erased_addresses_are_not_reimported(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    app.post_gdpr("erasure", "ursula@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app
+        .post_subscribers_import(
+            "name,email\nUrsula,URSULA@example.com\nOctavia,octavia@example.com\n".into(),
+            "confirmed",
+        )
+        .await;
+
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["errors"][0]["row"], 1);
+}
@@ -1,4 +1,4 @@
-use crate::helpers::{test_app, TestApp};
+use crate::helpers::{spawn_app, test_app, TestApp};
 use rstest::*;
 
 #[rstest]
@@ -15,3 +15,93 @@ async fn health_check_works(#[future] test_app: TestApp) {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+async fn get_readiness(app: &TestApp) -> reqwest::Response {
+    reqwest::get(format!("{}/health/ready", &app.address))
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[rstest]
+#[tokio::test]
+async fn liveness_does_not_depend_on_anything(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::get(format!("{}/health/live", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn readiness_reports_every_required_dependency(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = get_readiness(&app).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    for component in ["database", "migrations"] {
+        assert_eq!(body["components"][component]["status"], "up");
+        assert_eq!(body["components"][component]["required"], true);
+        assert!(body["components"][component]["latency_ms"].is_u64());
+    }
+    // Not probed unless enabled.
+    assert!(body["components"].get("email_provider").is_none());
+}
+
+#[rstest]
+#[tokio::test]
+async fn pending_migrations_make_the_instance_unready(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT max(version) FROM _sqlx_migrations)",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = get_readiness(&app).await;
+
+    assert_eq!(503, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["components"]["database"]["status"], "up");
+    assert_eq!(body["components"]["migrations"]["status"], "down");
+    assert_eq!(
+        body["components"]["migrations"]["error"],
+        "1 migration(s) have not been applied."
+    );
+}
+
+#[tokio::test]
+async fn an_unreachable_email_provider_is_reported_but_not_required() {
+    let app = spawn_app(|c| {
+        c.health.probe_email_provider = true;
+        // Nothing listens on the discard port.
+        c.email_client.base_url = "http://127.0.0.1:9".into();
+    })
+    .await;
+
+    let response = get_readiness(&app).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let email_provider = &body["components"]["email_provider"];
+    assert_eq!(email_provider["status"], "down");
+    assert_eq!(email_provider["required"], false);
+    assert_eq!(
+        email_provider["error"],
+        "Failed to reach the email provider."
+    );
+}
+
+#[tokio::test]
+async fn a_reachable_email_provider_is_up() {
+    let app = spawn_app(|c| c.health.probe_email_provider = true).await;
+
+    let body: serde_json::Value = get_readiness(&app).await.json().await.unwrap();
+
+    assert_eq!(body["components"]["email_provider"]["status"], "up");
+}
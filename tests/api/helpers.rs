@@ -1,6 +1,12 @@
+use opentelemetry_sdk::trace::SdkTracerProvider;
 use rstest::*;
-use sqlx::PgPool;
+use secrecy::{ExposeSecret, SecretBox};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use uuid::Uuid;
 use wiremock::MockServer;
+use zero2prod::authentication::{compute_password_hash, Role};
+use zero2prod::configuration::{DatabasesSettings, Settings};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 use zero2prod::{
     configuration::get_configuration,
@@ -9,15 +15,80 @@ use zero2prod::{
 
 pub struct TestApp {
     pub address: String,
+    /// Where `/metrics` is served: `address`, unless an admin port is configured.
+    pub metrics_address: String,
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub port: u16,
+    pub test_user: TestUser,
+}
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self::generate_with_role(Role::Admin)
+    }
+
+    pub fn generate_with_role(role: Role) -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+            role,
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let password_hash = compute_password_hash(SecretBox::new(Box::new(self.password.clone())))
+            .expect("Failed to hash the test user password.");
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash, role) VALUES ($1, $2, $3, $4)",
+            self.user_id,
+            self.username,
+            password_hash.expose_secret(),
+            self.role.as_str(),
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
 }
 
 impl TestApp {
+    pub async fn create_user(&self, role: Role) -> TestUser {
+        let user = TestUser::generate_with_role(role);
+        user.store(&self.db_pool).await;
+        user
+    }
+
+    pub async fn get_form_token(&self) -> String {
+        let body: serde_json::Value = reqwest::Client::new()
+            .get(format!("{}/api/v1/subscriptions/form-token", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .expect("Failed to parse the form token response.");
+        body["form_token"].as_str().unwrap().to_owned()
+    }
+
+    /// Submits the subscription form, the way a browser would after rendering it.
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        let form_token = self.get_form_token().await;
+        self.post_subscriptions_raw(format!("{body}&form_token={form_token}"))
+            .await
+    }
+
+    pub async fn post_subscriptions_raw(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -25,6 +96,26 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Subscribes through the JSON API, the way our apps do.
+    pub async fn post_subscriptions_json(&self, body: serde_json::Value) -> reqwest::Response {
+        let mut body = body;
+        body["form_token"] = self.get_form_token().await.into();
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/subscriptions", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> String {
+        let response = reqwest::get(format!("{}/metrics", &self.metrics_address))
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(200, response.status().as_u16());
+        response.text().await.unwrap()
+    }
+
     pub fn get_confirmation_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -41,12 +132,123 @@ impl TestApp {
             confirmation_link
         };
 
-        get_link(&body["Content"]["content"].as_str().unwrap())
+        get_link(body["Content"]["content"].as_str().unwrap())
     }
 
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/api/v1/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscribers_import(&self, body: String, mode: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/admin/subscribers/import", &self.address))
+            .query(&[("mode", mode)])
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Content-Type", "text/csv")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_gdpr(&self, operation: &str, email: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/admin/gdpr/{}", &self.address, operation))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/api/v1/admin/audit", &self.address))
+            .query(query)
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_export(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/api/v1/admin/subscribers/export", &self.address))
+            .query(query)
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/admin/password", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_invitation(
+        &self,
+        user: &TestUser,
+        email: &str,
+        role: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/admin/users/invitations", &self.address))
+            .basic_auth(&user.username, Some(&user.password))
+            .json(&serde_json::json!({ "email": email, "role": role }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_log_filter(&self, user: &TestUser, filter: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!("{}/api/v1/admin/log-filter", &self.address))
+            .basic_auth(&user.username, Some(&user.password))
+            .json(&serde_json::json!({ "filter": filter }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// The requests received by the email server once there are `count` of
+    /// them, as some emails are sent in the background.
+    pub async fn received_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..100 {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= count {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("The email server did not receive {count} emails.");
+    }
+
+    /// Submits `form` the way the pages opened by emailed links do.
+    pub async fn post_form(&self, path: &str, form: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}{}", &self.address, path))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// POSTs `body` to an unauthenticated endpoint, such as accepting an invitation.
+    pub async fn post_json(&self, path: &str, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}{}", &self.address, path))
             .json(&body)
             .send()
             .await
@@ -54,36 +256,90 @@ impl TestApp {
     }
 }
 
+/// Extracts the `token` query parameter from a link sent by email.
+pub fn token_from_link(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+        .expect("The link has no token.")
+}
+
 #[fixture]
 #[once]
 fn set_log() {
-    if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber("test".into(), "debug".into(), std::io::stdout);
-        init_subscriber(subscriber);
+    let mut logging = get_configuration()
+        .expect("Failed to read configuration")
+        .logging;
+    logging.filter = "debug".into();
+    // Spans are not exported, but still get trace IDs to propagate.
+    let tracer_provider = SdkTracerProvider::builder().build();
+    let sink = if std::env::var("TEST_LOG").is_ok() {
+        BoxMakeWriter::new(std::io::stdout)
     } else {
-        let subscriber = get_subscriber("test".into(), "debug".into(), std::io::sink);
-        init_subscriber(subscriber);
-    }
+        BoxMakeWriter::new(std::io::sink)
+    };
+    let (subscriber, log_filter, _) =
+        get_subscriber("test".into(), &logging, sink, &tracer_provider)
+            .expect("Failed to set up logging");
+    init_subscriber(subscriber, log_filter);
 }
 
 #[fixture]
-pub async fn test_app(_set_log: ()) -> TestApp {
+pub async fn test_app() -> TestApp {
+    spawn_app(|_| {}).await
+}
+
+/// Spawns an application with settings tweaked by `configure`.
+pub async fn spawn_app(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    set_log::get();
     let email_server = MockServer::start().await;
     let mut configuration = get_configuration().expect("Failed to read configuration");
+    configuration.database.database_name = Uuid::new_v4().to_string();
     configuration.application.port = 0;
     configuration.email_client.base_url = email_server.uri();
+    // Tests fill in forms faster than any person could.
+    configuration.bot_protection.min_fill_seconds = 0;
+    configure(&mut configuration);
+    configure_database(&configuration.database).await;
 
     let application = Application::build(&configuration)
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", &application_port);
-    let _ = tokio::spawn(application.run_until_stopped());
+    let metrics_address = match application.metrics_port() {
+        Some(port) => format!("http://127.0.0.1:{port}"),
+        None => address.clone(),
+    };
+    tokio::spawn(application.run_until_stopped());
 
-    TestApp {
+    let test_app = TestApp {
         address,
+        metrics_address,
         db_pool: get_connection_pool(&configuration.database),
         email_server,
         port: application_port,
-    }
+        test_user: TestUser::generate(),
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
+}
+
+async fn configure_database(config: &DatabasesSettings) -> PgPool {
+    let mut connection = PgConnection::connect_with(&config.without_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .expect("Failed to create database.");
+
+    let connection_pool = PgPool::connect_with(config.pg_connection())
+        .await
+        .expect("Failed to connect to Postgres.");
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .expect("Failed to migrate the database");
+    connection_pool
 }
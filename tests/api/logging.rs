@@ -0,0 +1,97 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use uuid::Uuid;
+use zero2prod::authentication::Role;
+use zero2prod::configuration::{get_configuration, LogFileSettings, LogFormat, LogRotation};
+use zero2prod::telemetry::get_subscriber;
+
+#[rstest]
+#[tokio::test]
+async fn admins_can_change_the_log_filter_at_runtime(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    // Other tests share the subscriber: keep logging their spans.
+    let response = app
+        .put_log_filter(&app.test_user, "debug,zero2prod=trace")
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["filter"], "zero2prod=trace,debug");
+    let body: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/api/v1/admin/log-filter", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["filter"], "zero2prod=trace,debug");
+    let entries: Vec<serde_json::Value> = app
+        .get_audit_log(&[("action", "logging.filter.update")])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(entries[0]["after"]["filter"], "debug,zero2prod=trace");
+
+    app.put_log_filter(&app.test_user, "debug")
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[rstest]
+#[tokio::test]
+async fn invalid_filter_directives_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = app.put_log_filter(&app.test_user, "sqlx=loudest").await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn only_admins_can_change_the_log_filter(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let publisher = app.create_user(Role::Publisher).await;
+
+    let response = app.put_log_filter(&publisher, "trace").await;
+
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[test]
+fn logs_are_also_written_to_rotating_files() {
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let mut logging = get_configuration().unwrap().logging;
+    logging.filter = "info".into();
+    logging.file = Some(LogFileSettings {
+        directory: directory.to_str().unwrap().into(),
+        file_name_prefix: "zero2prod".into(),
+        rotation: LogRotation::Daily,
+        format: LogFormat::Compact,
+    });
+    let tracer_provider = opentelemetry_sdk::trace::SdkTracerProvider::builder().build();
+    let (subscriber, _, guard) =
+        get_subscriber("test".into(), &logging, std::io::sink, &tracer_provider).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || tracing::info!("Written to a file"));
+    // Waits for the logs to reach the file.
+    drop(guard);
+
+    let files: Vec<_> = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    let file_name = files[0].file_name().unwrap().to_str().unwrap();
+    assert!(file_name.starts_with("zero2prod.") && file_name.ends_with(".log"));
+    let logs = std::fs::read_to_string(&files[0]).unwrap();
+    assert!(logs.contains("Written to a file"));
+    // Files are read with other tools than terminals.
+    assert!(!logs.contains('\u{1b}'));
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,144 @@
+use crate::helpers::{spawn_app, test_app, TestApp};
+use reqwest::header::{COOKIE, RETRY_AFTER, SET_COOKIE};
+use rstest::*;
+
+async fn login(app: &TestApp, password: &str) -> reqwest::Response {
+    app.post_json(
+        "/admin/login",
+        serde_json::json!({ "username": app.test_user.username, "password": password }),
+    )
+    .await
+}
+
+async fn get_audit_log_as(app: &TestApp, password: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/api/v1/admin/audit", &app.address))
+        .basic_auth(&app.test_user.username, Some(password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[rstest]
+#[tokio::test]
+async fn repeated_failed_logins_lock_the_account_out(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    for _ in 0..5 {
+        assert_eq!(401, login(&app, "wrong-password").await.status().as_u16());
+    }
+
+    // The right password is refused while the lockout lasts.
+    let response = login(&app, &app.test_user.password).await;
+
+    assert_eq!(429, response.status().as_u16());
+    // Rounded up, so that clients honouring it do not come back too early.
+    assert_eq!(response.headers()[RETRY_AFTER], "900");
+}
+
+#[rstest]
+#[tokio::test]
+async fn basic_auth_shares_the_lockout_of_the_login_form(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    for _ in 0..3 {
+        get_audit_log_as(&app, "wrong-password").await;
+    }
+    for _ in 0..2 {
+        login(&app, "wrong-password").await;
+    }
+
+    let response = get_audit_log_as(&app, &app.test_user.password).await;
+
+    assert_eq!(429, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    for _ in 0..4 {
+        login(&app, "wrong-password").await;
+    }
+    assert_eq!(
+        200,
+        login(&app, &app.test_user.password).await.status().as_u16()
+    );
+
+    for _ in 0..4 {
+        login(&app, "wrong-password").await;
+    }
+
+    assert_eq!(
+        200,
+        login(&app, &app.test_user.password).await.status().as_u16()
+    );
+}
+
+#[tokio::test]
+async fn failures_behind_a_trusted_proxy_are_counted_per_forwarded_client() {
+    let app = spawn_app(|c| {
+        c.rate_limit.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+        c.login_protection.max_failures_per_ip = 3;
+    })
+    .await;
+    let login_from = |forwarded_for: &'static str, username: String, password: String| {
+        reqwest::Client::new()
+            .post(format!("{}/admin/login", &app.address))
+            .header("X-Forwarded-For", forwarded_for)
+            .json(&serde_json::json!({ "username": username, "password": password }))
+            .send()
+    };
+    for i in 0..3 {
+        login_from("203.0.113.1", format!("user-{i}"), "wrong-password".into())
+            .await
+            .expect("Failed to execute request.");
+    }
+
+    let username = app.test_user.username.clone();
+    let password = app.test_user.password.clone();
+    let blocked = login_from("203.0.113.1", username.clone(), password.clone())
+        .await
+        .expect("Failed to execute request.");
+    let other_client = login_from("203.0.113.2", username, password)
+        .await
+        .expect("Failed to execute request.");
+
+    // Every client reaches the app through the same proxy: only the forwarded
+    // address tells them apart.
+    assert_eq!(429, blocked.status().as_u16());
+    assert_eq!(200, other_client.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn wrong_current_passwords_count_towards_the_lockout(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let response = login(&app, &app.test_user.password).await;
+    let cookie = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok()?.split(';').next())
+        .find(|v| v.starts_with("session="))
+        .expect("No session cookie was set.")
+        .to_string();
+    let change_password = |current_password: &str| {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/admin/password", &app.address))
+            .header(COOKIE, &cookie)
+            .json(&serde_json::json!({
+                "current_password": current_password,
+                "new_password": "a brand new passphrase",
+            }))
+            .send()
+    };
+    for _ in 0..5 {
+        let response = change_password("wrong-password").await.unwrap();
+        assert_eq!(400, response.status().as_u16());
+    }
+
+    // A stolen session cookie is no way around the lockout.
+    let response = change_password(&app.test_user.password).await.unwrap();
+
+    assert_eq!(429, response.status().as_u16());
+    assert!(response.headers().contains_key(RETRY_AFTER));
+}
@@ -1,5 +1,45 @@
+mod admin_password;
+mod admin_users;
+mod api_keys;
+mod audit;
+mod authorization;
+mod bot_protection;
+mod gdpr;
+// The modules below written before clippy ran on the tests are left as
+// they are rather than rewritten for style.
+#[allow(
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::useless_conversion
+)]
 mod health_check;
 mod helpers;
+mod logging;
+mod login_protection;
+mod metrics;
+#[allow(
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::useless_conversion
+)]
 mod newsletter;
+mod openapi;
+mod problem_details;
+mod rate_limit;
+mod request_id;
+mod subscribers_export;
+mod subscribers_import;
+#[allow(
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::useless_conversion
+)]
 mod subscriptions;
+#[allow(
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::useless_conversion
+)]
 mod subscriptions_confirm;
+mod telemetry;
+mod two_factor;
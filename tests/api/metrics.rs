@@ -0,0 +1,99 @@
+use crate::helpers::{spawn_app, test_app, TestApp};
+use rstest::*;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn assert_has_sample(metrics: &str, sample: &str) {
+    assert!(
+        metrics.lines().any(|line| line == sample),
+        "`{sample}` is not in:\n{metrics}"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn signups_and_confirmations_are_counted(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link(email_request);
+    // Following the link twice confirms the subscription once.
+    for _ in 0..2 {
+        reqwest::get(confirmation_link.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    let metrics = app.get_metrics().await;
+    assert_has_sample(&metrics, "subscriptions_created_total 1");
+    assert_has_sample(&metrics, "subscriptions_confirmed_total 1");
+    assert_has_sample(
+        &metrics,
+        r#"emails_sent_total{outcome="success",transport="http_api"} 1"#,
+    );
+    assert_has_sample(
+        &metrics,
+        r#"email_send_duration_seconds_count{transport="http_api"} 1"#,
+    );
+    assert_has_sample(
+        &metrics,
+        r#"http_request_duration_seconds_count{method="GET",route="/subscriptions/confirm",status="200"} 2"#,
+    );
+    assert_has_sample(&metrics, "newsletter_delivery_queue_depth 0");
+}
+
+#[rstest]
+#[tokio::test]
+async fn failed_sends_and_unknown_routes_are_recorded(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    reqwest::get(format!("{}/no-such-route/42", &app.address))
+        .await
+        .unwrap();
+
+    let metrics = app.get_metrics().await;
+    assert_has_sample(
+        &metrics,
+        r#"emails_sent_total{outcome="failure",transport="http_api"} 1"#,
+    );
+    assert_has_sample(
+        &metrics,
+        r#"http_request_duration_seconds_count{method="POST",route="/subscriptions",status="500"} 1"#,
+    );
+    assert_has_sample(
+        &metrics,
+        r#"http_request_duration_seconds_count{method="GET",route="unmatched",status="404"} 1"#,
+    );
+    assert!(metrics.contains(r#"db_pool_connections{state="max"}"#));
+}
+
+#[tokio::test]
+async fn metrics_can_be_kept_off_the_public_port() {
+    let app = spawn_app(|c| c.metrics.admin_port = Some(0)).await;
+    assert_ne!(app.address, app.metrics_address);
+
+    let public = reqwest::get(format!("{}/metrics", &app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(404, public.status().as_u16());
+    app.get_metrics().await;
+}
@@ -70,6 +70,28 @@ async fn newsletters_returns_400_for_invalid_data(
     )
 }
 
+#[rstest]
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="admin""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
 async fn create_unconfirmed_subscriber(app: &TestApp) -> reqwest::Url {
     let body = format!(
         "name={}&email={}",
@@ -0,0 +1,45 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+
+const SNAPSHOT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/api/snapshots/openapi_v1.json"
+);
+
+/// Fails when the API changes without the committed document being updated.
+/// Review the changes, then refresh it with `UPDATE_OPENAPI_SNAPSHOT=1 cargo test openapi`.
+#[rstest]
+#[tokio::test]
+async fn the_openapi_document_matches_the_snapshot(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::get(format!("{}/api/v1/openapi.json", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(document["openapi"], "3.1.0");
+    let generated = serde_json::to_string_pretty(&document).unwrap() + "\n";
+    if std::env::var_os("UPDATE_OPENAPI_SNAPSHOT").is_some() {
+        std::fs::write(SNAPSHOT, &generated).expect("Failed to update the snapshot.");
+    }
+    let committed = std::fs::read_to_string(SNAPSHOT).unwrap_or_default();
+    assert!(
+        generated == committed,
+        "The OpenAPI document differs from {SNAPSHOT}. If the change is intended, \
+        update the snapshot with `UPDATE_OPENAPI_SNAPSHOT=1 cargo test openapi`."
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn unversioned_routes_are_kept_for_existing_clients(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::get(format!("{}/subscriptions/form-token", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+}
@@ -0,0 +1,97 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use sqlx::Executor;
+
+fn assert_is_problem(response: &reqwest::Response) {
+    assert_eq!(
+        "application/problem+json",
+        response.headers()["Content-Type"]
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn client_errors_are_described_as_problems(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=not-an-email".into())
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_is_problem(&response);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["status"], 400);
+    assert!(problem["instance"]
+        .as_str()
+        .unwrap()
+        .starts_with("urn:uuid:"));
+    // Error-specific members are kept.
+    assert_eq!(problem["errors"][0]["field"], "email");
+}
+
+#[rstest]
+#[tokio::test]
+async fn server_errors_do_not_reveal_their_cause(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    // Sabotage the database.
+    app.db_pool
+        .execute("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;")
+        .await
+        .unwrap();
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(500, response.status().as_u16());
+    assert_is_problem(&response);
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("subscription_token"), "{body}");
+    let problem: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem["title"], "Internal Server Error");
+    assert!(problem.get("detail").is_none());
+}
+
+#[rstest]
+#[tokio::test]
+async fn unknown_routes_are_problems_too(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::get(format!("{}/no-such-route", &app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(404, response.status().as_u16());
+    assert_is_problem(&response);
+}
+
+#[rstest]
+#[tokio::test]
+async fn browsers_get_an_html_error_page(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=<unknown>",
+            &app.address
+        ))
+        .header(
+            "Accept",
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        "text/html; charset=utf-8",
+        response.headers()["Content-Type"]
+    );
+    let page = response.text().await.unwrap();
+    assert!(page.contains("<h1>Unauthorized</h1>"));
+    assert!(page.contains("There is no subscriber associated with the provided token."));
+}
@@ -0,0 +1,115 @@
+use crate::helpers::{spawn_app, TestApp};
+use reqwest::header::RETRY_AFTER;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{RateLimitStoreKind, TokenBucketSettings};
+
+const LIMIT: TokenBucketSettings = TokenBucketSettings {
+    capacity: 2,
+    refill_per_minute: 1,
+};
+
+const SINGLE_ATTEMPT: TokenBucketSettings = TokenBucketSettings {
+    capacity: 1,
+    refill_per_minute: 1,
+};
+
+async fn app_with_limits(
+    store: RateLimitStoreKind,
+    trusted_proxies: &[&str],
+    per_email: TokenBucketSettings,
+) -> TestApp {
+    let trusted_proxies = trusted_proxies
+        .iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect();
+    let app = spawn_app(|c| {
+        c.rate_limit.store = store;
+        c.rate_limit.trusted_proxies = trusted_proxies;
+        c.rate_limit.per_ip = LIMIT;
+        c.rate_limit.per_email = per_email;
+    })
+    .await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+async fn subscribe_from(app: &TestApp, forwarded_for: &str, email: &str) -> reqwest::Response {
+    let form_token = app.get_form_token().await;
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", forwarded_for)
+        .body(format!(
+            "name=le%20guin&email={email}&form_token={form_token}"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn subscribing_too_often_to_the_same_address_is_rejected() {
+    for store in [RateLimitStoreKind::Memory, RateLimitStoreKind::Postgres] {
+        let app = app_with_limits(store, &["127.0.0.1"], SINGLE_ATTEMPT).await;
+        let response = subscribe_from(&app, "203.0.113.1", "ursula%40example.com").await;
+        assert_eq!(200, response.status().as_u16());
+
+        // Changing the client or the case of the address does not get around the limit.
+        let response = subscribe_from(&app, "203.0.113.2", "Ursula%40example.com").await;
+
+        assert_eq!(429, response.status().as_u16(), "with the {store:?} store");
+        assert_eq!(response.headers()[RETRY_AFTER], "60");
+    }
+}
+
+#[tokio::test]
+async fn subscribing_too_often_from_the_same_ip_is_rejected() {
+    let app = app_with_limits(RateLimitStoreKind::Memory, &["127.0.0.1"], LIMIT).await;
+    for i in 0..2 {
+        let email = format!("user-{i}%40example.com");
+        let response = subscribe_from(&app, "203.0.113.1", &email).await;
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    let blocked = subscribe_from(&app, "203.0.113.1", "user-2%40example.com").await;
+    let other_client = subscribe_from(&app, "203.0.113.2", "user-3%40example.com").await;
+
+    assert_eq!(429, blocked.status().as_u16());
+    assert_eq!(200, other_client.status().as_u16());
+}
+
+#[tokio::test]
+async fn forwarded_for_is_ignored_without_a_trusted_proxy() {
+    let app = app_with_limits(RateLimitStoreKind::Memory, &[], LIMIT).await;
+    for i in 0..2 {
+        let email = format!("user-{i}%40example.com");
+        subscribe_from(&app, &format!("203.0.113.{i}"), &email).await;
+    }
+
+    let response = subscribe_from(&app, "203.0.113.9", "user-9%40example.com").await;
+
+    assert_eq!(429, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn concurrent_requests_share_the_postgres_bucket() {
+    let app = app_with_limits(RateLimitStoreKind::Postgres, &["127.0.0.1"], SINGLE_ATTEMPT).await;
+    let requests = (0..5).map(|i| {
+        let forwarded_for = format!("203.0.113.{i}");
+        let app = &app;
+        async move { subscribe_from(app, &forwarded_for, "ursula%40example.com").await }
+    });
+
+    let responses = futures_util::future::join_all(requests).await;
+
+    let admitted = responses
+        .iter()
+        .filter(|response| response.status().as_u16() != 429)
+        .count();
+    assert_eq!(1, admitted);
+}
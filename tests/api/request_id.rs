@@ -0,0 +1,112 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn request_id(response: &reqwest::Response) -> Uuid {
+    let header = response.headers()["X-Request-Id"].to_str().unwrap();
+    Uuid::parse_str(header).expect("The request ID is not a UUID.")
+}
+
+#[rstest]
+#[tokio::test]
+async fn every_response_carries_a_request_id(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let first = reqwest::get(format!("{}/health_check", &app.address))
+        .await
+        .unwrap();
+    let second = reqwest::get(format!("{}/health_check", &app.address))
+        .await
+        .unwrap();
+
+    assert_ne!(request_id(&first), request_id(&second));
+}
+
+#[rstest]
+#[tokio::test]
+async fn the_request_id_of_the_client_is_kept_and_quoted_in_errors(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let id = Uuid::new_v4();
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/not-a-route", &app.address))
+        .header("X-Request-Id", id.to_string())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(404, response.status().as_u16());
+    assert_eq!(request_id(&response), id);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["instance"], format!("urn:uuid:{id}"));
+}
+
+#[rstest]
+#[tokio::test]
+async fn request_ids_that_are_not_uuids_are_replaced(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", &app.address))
+        .header("X-Request-Id", "<script>")
+        .send()
+        .await
+        .unwrap();
+
+    // Parsing as a UUID is the assertion.
+    request_id(&response);
+}
+
+#[rstest]
+#[tokio::test]
+async fn the_request_id_is_passed_on_to_the_email_provider_and_the_audit_log(
+    #[future] test_app: TestApp,
+) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link(email_request);
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let id = Uuid::new_v4();
+
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .header("X-Request-Id", id.to_string())
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": "<p>Newsletter body as HTML</p>",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let newsletter_request = &app.email_server.received_requests().await.unwrap()[1];
+    assert_eq!(
+        newsletter_request.headers["X-Request-Id"],
+        id.to_string().as_str()
+    );
+    let entries: Vec<serde_json::Value> = app
+        .get_audit_log(&[("action", "newsletter.publish")])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(entries[0]["request_id"], id.to_string());
+}
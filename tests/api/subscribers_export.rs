@@ -0,0 +1,153 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn seed_subscribers(app: &TestApp) {
+    app.post_subscribers_import(
+        "name,email\nUrsula,ursula@example.com\nOctavia,octavia@example.com\n".into(),
+        "confirmed",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscribers_import(
+        "name,email\nTerry,terry@example.org\n".into(),
+        "send_confirmation",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+#[rstest]
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::get(format!("{}/api/v1/admin/subscribers/export", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn csv_export_contains_the_selected_columns(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    seed_subscribers(&app).await;
+
+    let response = app
+        .get_subscribers_export(&[("format", "csv"), ("columns", "email,status")])
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "text/csv; charset=utf-8",
+        response.headers()["Content-Type"]
+    );
+    let body = response.text().await.unwrap();
+    let mut lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines.remove(0), "email,status");
+    lines.sort();
+    assert_eq!(
+        lines,
+        vec![
+            "octavia@example.com,confirmed",
+            "terry@example.org,pending_confirmation",
+            "ursula@example.com,confirmed",
+        ]
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn jsonl_export_applies_the_filters(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    seed_subscribers(&app).await;
+
+    let response = app
+        .get_subscribers_export(&[
+            ("format", "jsonl"),
+            ("status", "confirmed"),
+            ("email", "URSULA"),
+        ])
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["email"], "ursula@example.com");
+    assert_eq!(rows[0]["name"], "Ursula");
+    assert_eq!(rows[0]["status"], "confirmed");
+}
+
+#[rstest]
+#[tokio::test]
+async fn like_wildcards_in_the_email_filter_are_matched_literally(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    seed_subscribers(&app).await;
+
+    for filter in ["%", "a_"] {
+        let response = app
+            .get_subscribers_export(&[("format", "jsonl"), ("email", filter)])
+            .await;
+
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!(response.text().await.unwrap(), "", "Filter: {filter}");
+    }
+}
+
+#[rstest]
+#[tokio::test]
+async fn csv_cells_are_not_exported_as_formulas(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    app.post_subscribers_import(
+        "name,email\n=1+1,ursula@example.com\n@Octavia,octavia@example.com\n".into(),
+        "confirmed",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let response = app
+        .get_subscribers_export(&[("format", "csv"), ("columns", "name")])
+        .await;
+
+    let body = response.text().await.unwrap();
+    let mut lines: Vec<&str> = body.lines().collect();
+    lines.sort();
+    assert_eq!(lines, vec!["'=1+1", "'@Octavia", "name"]);
+}
+
+#[rstest]
+#[case(&[("columns", "email,password")], "unknown column")]
+#[case(&[("format", "xml")], "unknown format")]
+#[case(&[("status", "maybe")], "unknown status")]
+#[tokio::test]
+async fn export_returns_400_for_invalid_parameters(
+    #[future] test_app: TestApp,
+    #[case] query: &[(&str, &str)],
+    #[case] error_message: &str,
+) {
+    let app = test_app.await;
+
+    let response = app.get_subscribers_export(query).await;
+
+    assert_eq!(
+        400,
+        response.status().as_u16(),
+        "The API did not fail with 400 Bad Request for an {error_message}."
+    );
+}
@@ -0,0 +1,223 @@
+use crate::helpers::{test_app, TestApp};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
+use rstest::*;
+use wiremock::matchers::{any, body_string_contains, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// The audit log entry recorded once the confirmations of an import are sent.
+async fn confirmations_audit_event(app: &TestApp) -> serde_json::Value {
+    for _ in 0..100 {
+        let entries: Vec<serde_json::Value> = app
+            .get_audit_log(&[("action", "subscribers.import.confirmations")])
+            .await
+            .json()
+            .await
+            .unwrap();
+        if let Some(entry) = entries.into_iter().next() {
+            return entry;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("The confirmations of the import were not audited.");
+}
+
+fn csv_rows(count: usize) -> String {
+    let mut body = "name,email\n".to_string();
+    for _ in 0..count {
+        body.push_str(&format!(
+            "{},{}\n",
+            Name().fake::<String>(),
+            SafeEmail().fake::<String>()
+        ));
+    }
+    body
+}
+
+#[rstest]
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/subscribers/import", &app.address))
+        .body(csv_rows(1))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="admin""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn requests_with_a_wrong_password_are_rejected(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/subscribers/import", &app.address))
+        .basic_auth(&app.test_user.username, Some("not-the-password"))
+        .body(csv_rows(1))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn import_as_confirmed_stores_subscribers_without_sending_emails(
+    #[future] test_app: TestApp,
+) {
+    let app = test_app.await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscribers_import(csv_rows(3), "confirmed").await;
+
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 3);
+    assert_eq!(report["failed"], 0);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 3);
+    assert!(saved.iter().all(|r| r.status == "confirmed"));
+}
+
+#[rstest]
+#[tokio::test]
+async fn import_with_confirmation_sends_an_email_to_each_subscriber(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribers_import(csv_rows(2), "send_confirmation")
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    // The emails are sent once the import has answered.
+    app.received_emails(2).await;
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert!(saved.iter().all(|r| r.status == "pending_confirmation"));
+}
+
+#[rstest]
+#[tokio::test]
+async fn import_audits_the_rows_whose_confirmation_could_not_be_sent(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .and(body_string_contains("ursula@example.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = format!(
+        "name,email\n\
+        Tom,{}\n\
+        Ursula,ursula@example.com\n",
+        SafeEmail().fake::<String>()
+    );
+
+    let response = app.post_subscribers_import(body, "send_confirmation").await;
+
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["failed"], 0);
+    let event = confirmations_audit_event(&app).await;
+    assert_eq!(event["after"]["sent"], 1);
+    assert_eq!(event["after"]["unsent_rows"], serde_json::json!([2]));
+}
+
+#[rstest]
+#[tokio::test]
+async fn import_reports_invalid_and_duplicated_rows(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let email: String = SafeEmail().fake();
+    let body = format!(
+        "name,email\n\
+        Ursula,{email}\n\
+        Ursula,definitely-not-an-email\n\
+        ,{}\n\
+        Ursula again,{email}\n",
+        SafeEmail().fake::<String>()
+    );
+
+    let response = app.post_subscribers_import(body, "confirmed").await;
+
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["failed"], 3);
+    let failed_rows: Vec<u64> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["row"].as_u64().unwrap())
+        .collect();
+    assert_eq!(failed_rows, vec![2, 3, 4]);
+}
+
+#[rstest]
+#[tokio::test]
+async fn import_treats_aliases_of_a_mailbox_as_duplicates(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let body = "name,email\n\
+        Ursula,u.le.guin@gmail.com\n\
+        Ursula,U.Le.Guin@Gmail.com\n\
+        Ursula,uleguin+news@googlemail.com\n"
+        .to_string();
+
+    let response = app.post_subscribers_import(body, "confirmed").await;
+
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["failed"], 2);
+}
+
+#[rstest]
+#[tokio::test]
+async fn import_applies_the_email_domain_policy_of_signups(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let body = format!(
+        "name,email\n\
+        Ursula,ursula@mailinator.com\n\
+        Tom,{}\n",
+        SafeEmail().fake::<String>()
+    );
+
+    let response = app.post_subscribers_import(body, "confirmed").await;
+
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["errors"][0]["row"], 1);
+}
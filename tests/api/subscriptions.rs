@@ -1,4 +1,4 @@
-use crate::helpers::{test_app, TestApp};
+use crate::helpers::{spawn_app, test_app, TestApp};
 use fake::faker::internet::en::SafeEmail;
 use fake::faker::name::en::Name;
 use fake::Fake;
@@ -55,6 +55,104 @@ async fn subscribe_persists_the_new_subscriber(#[future] test_app: TestApp) {
     assert_eq!(saved.status, "pending_confirmation");
 }
 
+#[rstest]
+#[tokio::test]
+async fn subscribe_accepts_json_and_returns_the_subscription(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let fake_name: String = Name().fake();
+    let fake_email: String = SafeEmail().fake();
+
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(serde_json::json!({"name": fake_name, "email": fake_email}))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(body["id"], saved.id.to_string());
+    assert_eq!(saved.email, fake_email);
+}
+
+#[rstest]
+#[tokio::test]
+async fn subscribe_does_not_return_the_id_of_a_stored_subscriber(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({"name": "le guin", "email": "ursula@example.com"});
+
+    let first: serde_json::Value = app
+        .post_subscriptions_json(body.clone())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let second: serde_json::Value = app
+        .post_subscriptions_json(body)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(first["id"], saved.id.to_string());
+    assert_ne!(second["id"], saved.id.to_string());
+    assert_eq!(first["status"], second["status"]);
+}
+
+#[rstest]
+#[tokio::test]
+async fn subscribe_validates_json_like_forms(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = app
+        .post_subscriptions_json(serde_json::json!({"name": "", "email": "not-an-email"}))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let fields: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["name", "email"]);
+}
+
+#[rstest]
+#[tokio::test]
+async fn subscribe_rejects_other_content_types_with_a_415(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriptions", &app.address))
+        .header("Content-Type", "text/plain")
+        .body("name=le guin&email=ursula_le_guin@gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(415, response.status().as_u16());
+}
+
 #[rstest]
 #[case(format!("name={}",Name().fake::<String>()), "missing the email")]
 #[case(format!("email={}",SafeEmail().fake::<String>()), "missing the name")]
@@ -79,6 +177,36 @@ async fn subscribe_400_rt(
     );
 }
 
+#[rstest]
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let long_name = "a".repeat(257);
+
+    let response = app
+        .post_subscriptions(format!("name={long_name}&email=definitely-not-an-email"))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["errors"],
+        serde_json::json!([
+            {
+                "field": "name",
+                "code": "too_long",
+                "message": "The name is 257 characters long, longer than the maximum of 256.",
+                "params": { "max": 256, "actual": 257 }
+            },
+            {
+                "field": "email",
+                "code": "invalid_email_syntax",
+                "message": "The email is not a valid address."
+            }
+        ])
+    );
+}
+
 #[rstest]
 #[tokio::test]
 async fn subscribe_sends_a_confirmation_email_for_valid_data(#[future] test_app: TestApp) {
@@ -120,3 +248,171 @@ async fn subscribe_sends_a_confirmation_email_with_a_link(#[future] test_app: Te
     let confirmation_links = app.get_confirmation_link(&email_request);
     assert!(confirmation_links.as_str().starts_with("http://"))
 }
+
+#[rstest]
+#[tokio::test]
+async fn subscribe_rejects_disposable_email_providers(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mailinator.com".into())
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["code"], "disposable_domain");
+}
+
+#[tokio::test]
+async fn subscribe_applies_the_configured_domain_lists() {
+    let app = spawn_app(|c| {
+        c.email_validation.denied_domains = vec!["example.com".into()];
+        c.email_validation.allowed_domains = vec!["yopmail.com".into()];
+    })
+    .await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let denied = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+    let allowed = app
+        .post_subscriptions("name=le%20guin&email=ursula%40yopmail.com".into())
+        .await;
+
+    assert_eq!(400, denied.status().as_u16());
+    let body: serde_json::Value = denied.json().await.unwrap();
+    assert_eq!(
+        body["errors"][0],
+        serde_json::json!({
+            "field": "email",
+            "code": "denied_domain",
+            "message": "Email addresses at example.com are not accepted.",
+            "params": { "domain": "example.com" }
+        })
+    );
+    assert_eq!(200, allowed.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn subscribe_stores_the_email_as_typed_and_in_canonical_form(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let first = app
+        .post_subscriptions("name=le%20guin&email=%20Ursula%40Example.COM".into())
+        .await;
+    let second = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+    let saved = sqlx::query!("SELECT email, email_canonical FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "Ursula@example.com");
+    assert_eq!(saved[0].email_canonical, "ursula@example.com");
+}
+
+#[rstest]
+#[tokio::test]
+async fn subscribing_again_while_pending_resends_the_confirmation(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula.le.guin%40gmail.com".into())
+        .await;
+    let alias = app
+        .post_subscriptions("name=le%20guin&email=ursulaleguin%2Bnews%40gmail.com".into())
+        .await;
+
+    assert_eq!(200, alias.status().as_u16());
+    let first: serde_json::Value = first.json().await.unwrap();
+    let alias: serde_json::Value = alias.json().await.unwrap();
+    assert_ne!(alias["id"], first["id"]);
+    assert_eq!(alias["status"], "pending_confirmation");
+    let emails = app.received_emails(2).await;
+    let link = app.get_confirmation_link(&emails[1]);
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn provider_aliases_are_different_subscribers_when_not_canonicalized() {
+    let app = spawn_app(|c| c.email_validation.canonicalize_provider_aliases = false).await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula.le.guin%40gmail.com".into())
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursulaleguin%2Bnews%40gmail.com".into())
+        .await;
+
+    let saved = sqlx::query!("SELECT email_canonical FROM subscriptions ORDER BY email_canonical")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    let saved: Vec<_> = saved.into_iter().map(|r| r.email_canonical).collect();
+    assert_eq!(
+        saved,
+        ["ursula.le.guin@gmail.com", "ursulaleguin+news@gmail.com"]
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn subscribing_again_once_confirmed_sends_nothing_and_tells_nothing(
+    #[future] test_app: TestApp,
+) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+    let emails = app.received_emails(1).await;
+    reqwest::get(app.get_confirmation_link(&emails[0]))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula%40example.com".into())
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_ne!(body["id"], saved.id.to_string());
+}
@@ -0,0 +1,81 @@
+use crate::helpers::{test_app, TestApp};
+use rstest::*;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{get_configuration, TracingSettings};
+use zero2prod::telemetry::{get_subscriber, get_tracer_provider};
+
+#[rstest]
+#[tokio::test]
+async fn the_trace_of_a_request_is_carried_over_to_the_email_provider(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    Mock::given(path("/v1.0/me/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let form_token = app.get_form_token().await;
+
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01"))
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("form_token", &form_token),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request
+        .headers
+        .get("traceparent")
+        .expect("The email request carries no trace context.")
+        .to_str()
+        .unwrap();
+    let fields: Vec<_> = traceparent.split('-').collect();
+    assert_eq!(fields[1], trace_id);
+    // The parent is our span, not the caller's.
+    assert_ne!(fields[2], "00f067aa0ba902b7");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_are_exported_to_the_configured_collector() {
+    let collector = MockServer::start().await;
+    Mock::given(path("/v1/traces"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1..)
+        .mount(&collector)
+        .await;
+    let settings = TracingSettings {
+        otlp_endpoint: Some(collector.uri()),
+        export_timeout_milliseconds: 2000,
+    };
+    let tracer_provider = get_tracer_provider("test", &settings).unwrap();
+    let logging = get_configuration().unwrap().logging;
+    let (subscriber, _, _) =
+        get_subscriber("test".into(), &logging, std::io::sink, &tracer_provider).unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("exported").in_scope(|| {});
+    });
+    // Exports happen on a dedicated thread, which flushing waits for.
+    tokio::task::spawn_blocking(move || tracer_provider.force_flush())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let export = &collector.received_requests().await.unwrap()[0];
+    assert_eq!(
+        export.headers.get("Content-Type").unwrap(),
+        "application/x-protobuf"
+    );
+    // Protobuf keeps strings as they are.
+    assert!(export.body.windows(8).any(|w| w == b"exported"));
+}
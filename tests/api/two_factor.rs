@@ -0,0 +1,271 @@
+use crate::helpers::{test_app, TestApp};
+use reqwest::header::{COOKIE, RETRY_AFTER, SET_COOKIE};
+use rstest::*;
+use totp_rs::TOTP;
+
+/// Returns the `name=value` pair of the session cookie set by `response`.
+fn session_cookie(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find(|v| v.starts_with("session="))
+        .and_then(|v| v.split(';').next())
+        .expect("No session cookie was set.")
+        .to_string()
+}
+
+/// A code for the next time step: the current one was spent confirming the enrolment.
+fn next_code(totp: &TOTP) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp.generate(now + totp.step)
+}
+
+async fn enroll(app: &TestApp) -> (TOTP, Vec<String>) {
+    let client = reqwest::Client::new();
+    let enrollment: serde_json::Value = client
+        .post(format!("{}/api/v1/admin/2fa/enroll", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let totp = TOTP::from_url(enrollment["otpauth_uri"].as_str().unwrap()).unwrap();
+    let confirmation: serde_json::Value = client
+        .post(format!("{}/api/v1/admin/2fa/confirm", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "code": totp.generate_current().unwrap() }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let recovery_codes = confirmation["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c.as_str().unwrap().to_string())
+        .collect();
+    (totp, recovery_codes)
+}
+
+async fn login(app: &TestApp) -> reqwest::Response {
+    app.post_json(
+        "/admin/login",
+        serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }),
+    )
+    .await
+}
+
+async fn submit_second_factor(
+    app: &TestApp,
+    cookie: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/login/second-factor", &app.address))
+        .header(COOKIE, cookie)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_audit_log_with_cookie(app: &TestApp, cookie: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/api/v1/admin/audit", &app.address))
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_login_without_two_factor_starts_a_session(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = login(&app).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let cookie = session_cookie(&response);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["second_factor_required"], false);
+    assert_eq!(
+        200,
+        get_audit_log_with_cookie(&app, &cookie)
+            .await
+            .status()
+            .as_u16()
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn enrolment_returns_a_qr_code(#[future] test_app: TestApp) {
+    let app = test_app.await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/2fa/enroll", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    let enrollment: serde_json::Value = response.json().await.unwrap();
+    assert!(enrollment["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+    assert!(enrollment["qr_code_svg"].as_str().unwrap().contains("<svg"));
+}
+
+#[rstest]
+#[tokio::test]
+async fn basic_auth_is_rejected_once_two_factor_is_enabled(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    enroll(&app).await;
+
+    let response = app.get_audit_log(&[]).await;
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_session_is_only_usable_after_the_second_factor(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (totp, _) = enroll(&app).await;
+
+    let response = login(&app).await;
+    let pending_cookie = session_cookie(&response);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["second_factor_required"], true);
+    assert_eq!(
+        401,
+        get_audit_log_with_cookie(&app, &pending_cookie)
+            .await
+            .status()
+            .as_u16()
+    );
+
+    let response = submit_second_factor(
+        &app,
+        &pending_cookie,
+        serde_json::json!({ "code": next_code(&totp) }),
+    )
+    .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let cookie = session_cookie(&response);
+    assert_ne!(cookie, pending_cookie);
+    assert_eq!(
+        200,
+        get_audit_log_with_cookie(&app, &cookie)
+            .await
+            .status()
+            .as_u16()
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn a_totp_code_cannot_be_replayed(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (totp, _) = enroll(&app).await;
+    let code = next_code(&totp);
+    let first_cookie = session_cookie(&login(&app).await);
+    submit_second_factor(&app, &first_cookie, serde_json::json!({ "code": code }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let second_cookie = session_cookie(&login(&app).await);
+    let response =
+        submit_second_factor(&app, &second_cookie, serde_json::json!({ "code": code })).await;
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn recovery_codes_can_only_be_used_once(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (_, recovery_codes) = enroll(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+    let body = serde_json::json!({ "recovery_code": recovery_codes[0].to_uppercase() });
+
+    let first_cookie = session_cookie(&login(&app).await);
+    let first = submit_second_factor(&app, &first_cookie, body.clone()).await;
+    let second_cookie = session_cookie(&login(&app).await);
+    let second = submit_second_factor(&app, &second_cookie, body).await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(401, second.status().as_u16());
+}
+
+#[rstest]
+#[tokio::test]
+async fn repeated_invalid_codes_lock_the_account(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let (totp, _) = enroll(&app).await;
+    let cookie = session_cookie(&login(&app).await);
+    for _ in 0..4 {
+        let response =
+            submit_second_factor(&app, &cookie, serde_json::json!({ "code": "000000" })).await;
+        assert_eq!(401, response.status().as_u16());
+    }
+    let response =
+        submit_second_factor(&app, &cookie, serde_json::json!({ "code": "000000" })).await;
+    assert_eq!(429, response.status().as_u16());
+
+    // Even a valid code is refused while the lockout lasts.
+    let response = submit_second_factor(
+        &app,
+        &cookie,
+        serde_json::json!({ "code": next_code(&totp) }),
+    )
+    .await;
+
+    assert_eq!(429, response.status().as_u16());
+    assert!(response.headers().contains_key(RETRY_AFTER));
+}
+
+#[rstest]
+#[tokio::test]
+async fn logging_out_ends_the_session(#[future] test_app: TestApp) {
+    let app = test_app.await;
+    let cookie = session_cookie(&login(&app).await);
+
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/logout", &app.address))
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        401,
+        get_audit_log_with_cookie(&app, &cookie)
+            .await
+            .status()
+            .as_u16()
+    );
+}